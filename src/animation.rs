@@ -0,0 +1,73 @@
+//! time series of the physical field reconstructed from complex modes
+
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+
+use crate::writer;
+use crate::Dataset;
+
+/// write `frames` files of the physical field `Re(f e^{iφ})` for φ evenly spaced over
+/// `[0, 2π)`, along with a `.pvd` collection that paraview can animate
+///
+/// the frames are written next to `output` as `<stem>_0000.vtr, <stem>_0001.vtr, ...` (`.vtp`
+/// for line data) and the collection as `<stem>.pvd`. Derived arrays do not depend on the phase
+/// and are written to every frame unchanged
+pub(crate) fn write_phase_animation(output: &Path, frames: usize, dataset: &Dataset) -> Result<()> {
+    let Dataset {
+        axes,
+        layout,
+        fields,
+        derived,
+        ..
+    } = dataset;
+
+    if frames == 0 {
+        bail!("at least one phase frame is required");
+    }
+
+    if fields.iter().all(|field| field.imaginary.is_none()) {
+        println!("warning: no complex fields found, every phase frame will be identical");
+    }
+
+    let frame_base = output.with_extension(layout.extension());
+    let mut datasets = Vec::with_capacity(frames);
+
+    for frame in 0..frames {
+        let phase = 2. * std::f64::consts::PI * frame as f64 / frames as f64;
+        let path = writer::numbered_path(&frame_base, frame);
+
+        let arrays: Vec<_> = fields
+            .iter()
+            .map(|field| field.at_phase(phase))
+            .chain(derived.iter().map(writer::PointArray::reborrow))
+            .collect();
+
+        let file = std::fs::File::create(&path)
+            .with_context(|| format!("failed to create phase frame at {}", path.display()))?;
+        let file = std::io::BufWriter::new(file);
+
+        layout
+            .write(file, axes, &arrays)
+            .with_context(|| format!("failed to write phase frame {}", path.display()))?;
+
+        datasets.push((phase, path));
+    }
+
+    let collection_path = output.with_extension("pvd");
+    let collection = std::fs::File::create(&collection_path).with_context(|| {
+        format!(
+            "failed to create collection file at {}",
+            collection_path.display()
+        )
+    })?;
+
+    writer::write_collection(std::io::BufWriter::new(collection), &datasets)
+        .with_context(|| format!("failed to write {}", collection_path.display()))?;
+
+    println!(
+        "wrote {frames} phase frames and collection {}",
+        collection_path.display()
+    );
+
+    Ok(())
+}
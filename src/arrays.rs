@@ -0,0 +1,320 @@
+//! fields assembled from whole named arrays, for input formats that store arrays rather than
+//! rows of points
+//!
+//! the schema maps array names onto the grid the same way it maps CSV columns: the coordinates
+//! name 1D arrays of the grid lines (or 3D arrays varying along one direction, e.g. from
+//! `numpy.meshgrid(..., indexing="ij")`), and the columns of every field name arrays with the
+//! shape of the grid. Complex arrays give both the real and imaginary part of a field
+
+use anyhow::{bail, Context, Result};
+use ndarray::Array4;
+use std::collections::HashMap;
+
+use crate::fields::Field;
+use crate::grid;
+use crate::schema::{self, Schema};
+use crate::RawDataset;
+
+/// an n-dimensional array of numbers, converted to `f64` and stored in row major (C) order
+#[derive(Clone)]
+pub(crate) struct Array {
+    pub(crate) shape: Vec<usize>,
+    pub(crate) real: Vec<f64>,
+    /// the imaginary part of complex arrays
+    pub(crate) imaginary: Option<Vec<f64>>,
+}
+
+impl Array {
+    /// an array from values in column major (fortran) order, where the first index varies fastest
+    pub(crate) fn from_column_major(
+        shape: Vec<usize>,
+        real: Vec<f64>,
+        imaginary: Option<Vec<f64>>,
+    ) -> Self {
+        Self {
+            real: row_major(&real, &shape),
+            imaginary: imaginary.map(|imaginary| row_major(&imaginary, &shape)),
+            shape,
+        }
+    }
+}
+
+/// reorder the values of a column major array of `shape` into row major order
+fn row_major(values: &[f64], shape: &[usize]) -> Vec<f64> {
+    (0..values.len())
+        .map(|mut linear| {
+            // walk the row major index from the last (fastest) dimension, accumulating the
+            // column major offset where the first dimension is fastest
+            let mut offset = 0;
+            let mut stride: usize = shape.iter().product();
+            for length in shape.iter().rev() {
+                stride /= length;
+                offset += (linear % length) * stride;
+                linear /= length;
+            }
+            offset
+        })
+        .map(|offset| values[offset])
+        .collect()
+}
+
+/// a source of named arrays, such as the arrays of an npz archive
+pub(crate) trait Arrays {
+    /// what the arrays are, for messages
+    fn description(&self) -> String;
+
+    /// the names of every array
+    fn names(&self) -> Vec<&str>;
+
+    /// whether there is an array called `name`, including single values
+    fn contains(&self, name: &str) -> bool;
+
+    fn array(&mut self, name: &str) -> Result<Array>;
+}
+
+/// arrays that have already been read into memory, such as the variables of a fortran or MATLAB
+/// file
+pub(crate) struct Variables {
+    arrays: HashMap<String, Array>,
+    /// variable names in the order they were read
+    names: Vec<String>,
+    description: String,
+}
+
+impl Variables {
+    pub(crate) fn new(description: String) -> Self {
+        Self {
+            arrays: HashMap::new(),
+            names: Vec::new(),
+            description,
+        }
+    }
+
+    pub(crate) fn insert(&mut self, name: String, array: Array) {
+        if self.arrays.insert(name.clone(), array).is_none() {
+            self.names.push(name);
+        }
+    }
+}
+
+impl Arrays for Variables {
+    fn description(&self) -> String {
+        self.description.clone()
+    }
+
+    /// the names of every array, leaving out single values such as the grid dimensions so that
+    /// they are not detected as fields
+    fn names(&self) -> Vec<&str> {
+        self.names
+            .iter()
+            .filter(|name| {
+                self.arrays
+                    .get(*name)
+                    .is_some_and(|array| array.real.len() != 1)
+            })
+            .map(String::as_str)
+            .collect()
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        self.arrays.contains_key(name)
+    }
+
+    fn array(&mut self, name: &str) -> Result<Array> {
+        self.arrays.get(name).cloned().with_context(|| {
+            let available = self.names.join(", ");
+            format!(
+                "variable `{name}` is not in {} (available variables: {available})",
+                self.description
+            )
+        })
+    }
+}
+
+/// the shape of an array without its dimensions of length one, so that e.g. a `(nx, ny)` array
+/// matches a `(nx, ny, 1)` grid
+fn squeezed(shape: &[usize]) -> Vec<usize> {
+    shape.iter().copied().filter(|length| *length != 1).collect()
+}
+
+/// the grid lines along `direction` from a coordinate array
+fn axis_values(array: Array, name: &str, direction: usize) -> Result<Vec<f64>> {
+    let values = match array.shape[..] {
+        [] | [_] => array.real,
+        // the coordinate varies along `direction` of a 2D or 3D meshgrid
+        ref lengths if matches!(lengths.len(), 2 | 3) && direction < lengths.len() => {
+            let stride: usize = lengths[direction + 1..].iter().product();
+            (0..lengths[direction])
+                .map(|index| array.real[index * stride])
+                .collect()
+        }
+        _ => bail!(
+            "coordinate array `{name}` should be one dimensional or a meshgrid along its direction, but has shape {:?}",
+            array.shape
+        ),
+    };
+
+    if values.windows(2).any(|pair| pair[0] >= pair[1]) {
+        bail!("coordinate array `{name}` is not strictly increasing");
+    }
+
+    Ok(values)
+}
+
+/// read a field array, checking that it has the shape of the grid
+fn grid_array<A: Arrays>(source: &mut A, name: &str, lengths: [usize; 3]) -> Result<Array> {
+    let array = source.array(name)?;
+
+    if squeezed(&array.shape) != squeezed(&lengths) {
+        bail!(
+            "array `{name}` has shape {:?} but the grid is ({}, {}, {})",
+            array.shape,
+            lengths[0],
+            lengths[1],
+            lengths[2]
+        );
+    }
+
+    Ok(array)
+}
+
+/// read the rectilinear grid and every field of the schema from named arrays
+pub(crate) fn read<A: Arrays>(
+    source: &mut A,
+    mut schema: Schema,
+    tolerance: grid::Tolerance,
+) -> Result<RawDataset> {
+    // complex arrays are only known to be complex once they are read, so detected fields are
+    // printed after reading them
+    let detected = schema.fields.is_empty();
+    if detected {
+        schema.detect_fields(&source.names());
+    }
+    schema.validate()?;
+
+    let coordinates = schema.coordinates.clone();
+    // planar data such as PIV fields often has no z coordinate at all
+    let planar = !source.contains(&coordinates.z);
+    let description = source.description();
+
+    let mut axis = |direction: usize, name: &str| -> Result<grid::Axis> {
+        let values = axis_values(source.array(name)?, name, direction)?;
+        let length = values.len();
+
+        if length == 0 {
+            bail!("coordinate array `{name}` is empty, the input has no data rows");
+        }
+        let axis = grid::Axis::from_values(values, tolerance);
+
+        if axis.len() != length {
+            bail!("coordinate array `{name}` has grid lines closer together than the tolerance");
+        }
+
+        Ok(axis)
+    };
+
+    let axes = grid::Axes {
+        x: axis(0, &coordinates.x)?,
+        y: axis(1, &coordinates.y)?,
+        z: match planar {
+            true => {
+                let z = &coordinates.z;
+                println!("no `{z}` array in {description}, placing the 2D data at {z} = 0");
+                grid::Axis::from_values(vec![0.], tolerance)
+            }
+            false => axis(2, &coordinates.z)?,
+        },
+    };
+
+    let lengths = [axes.x.len(), axes.y.len(), axes.z.len()];
+    let [nx, ny, nz] = lengths;
+    println!("mesh size is ({nx},{ny},{nz})");
+
+    let mut fields = Vec::with_capacity(schema.fields.len());
+
+    for spec in &schema.fields {
+        let mut real = Vec::with_capacity(spec.components() * nx * ny * nz);
+        let mut imaginary = Vec::with_capacity(real.capacity());
+        let mut complex = false;
+
+        for name in &spec.real {
+            let array = grid_array(source, name, lengths)?;
+            let count = array.real.len();
+            real.extend(array.real);
+
+            // complex arrays carry their own imaginary part, real components of a complex
+            // field have none
+            match array.imaginary {
+                Some(part) => {
+                    complex = true;
+                    imaginary.extend(part);
+                }
+                None => imaginary.resize(imaginary.len() + count, 0.),
+            }
+        }
+
+        if let Some(names) = &spec.imaginary {
+            if complex {
+                bail!(
+                    "field `{}` has both complex arrays and separate imaginary arrays",
+                    spec.name
+                );
+            }
+
+            imaginary.clear();
+            for name in names {
+                imaginary.extend(grid_array(source, name, lengths)?.real);
+            }
+            complex = true;
+        }
+
+        let shape = (spec.components(), nx, ny, nz);
+        let real = Array4::from_shape_vec(shape, real)
+            .with_context(|| format!("field `{}` does not fit on the grid", spec.name))?;
+        let imaginary = if complex {
+            let imaginary = Array4::from_shape_vec(shape, imaginary)
+                .with_context(|| format!("field `{}` does not fit on the grid", spec.name))?;
+            Some(imaginary)
+        } else {
+            None
+        };
+
+        fields.push(Field {
+            name: spec.name.clone(),
+            real,
+            imaginary,
+        });
+    }
+
+    if detected {
+        let kinds: Vec<_> = fields
+            .iter()
+            .map(|field| {
+                let kind = schema::kind(field.components(), field.imaginary.is_some());
+                format!("{} ({kind})", field.name)
+            })
+            .collect();
+        println!("detected fields from {description}: {}", kinds.join(", "));
+    }
+
+    let time = match &schema.time {
+        Some(name) => {
+            let array = source.array(name)?;
+            let [time] = array.real[..] else {
+                bail!(
+                    "time array `{name}` should hold a single value, but has shape {:?}",
+                    array.shape
+                );
+            };
+            Some(time)
+        }
+        None => None,
+    };
+
+    Ok(RawDataset {
+        axes,
+        fields,
+        time,
+        layout: None,
+    })
+}
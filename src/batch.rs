@@ -0,0 +1,211 @@
+//! conversion of a series of CSV files into numbered output files and a `.pvd` collection
+
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+
+use crate::cli;
+use crate::schema::Schema;
+use crate::writer;
+
+/// every input file of the batch, in the order given by the glob or list file
+fn input_files(args: &cli::Args) -> Result<Vec<PathBuf>> {
+    if let Some(pattern) = &args.glob {
+        let paths = glob::glob(pattern)
+            .with_context(|| format!("invalid glob pattern `{pattern}`"))?
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("failed to read files matching `{pattern}`"))?;
+
+        return Ok(paths);
+    }
+
+    if let Some(list) = &args.list {
+        let contents = std::fs::read_to_string(list)
+            .with_context(|| format!("failed to read list file {}", list.display()))?;
+        let directory = list.parent().unwrap_or(Path::new(""));
+
+        let paths = contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| directory.join(line))
+            .collect();
+
+        return Ok(paths);
+    }
+
+    bail!("one of --csv-path, --glob or --list is required")
+}
+
+/// the last number in the file name of `path`, which may be signed and have an exponent, e.g.
+/// `0.25` for `case_t0.25.csv` or `-1e-3` for `t-1e-3.csv`
+fn time_from_filename(path: &Path) -> Result<f64> {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default();
+
+    let mut last = None;
+    let mut start = 0;
+    while start < stem.len() {
+        match number_length(&stem.as_bytes()[start..]) {
+            Some(length) => {
+                last = Some(&stem[start..start + length]);
+                start += length;
+            }
+            None => start += 1,
+        }
+    }
+
+    last.and_then(|number| number.parse().ok())
+        .with_context(|| format!("no number found in the file name of {}", path.display()))
+}
+
+/// length of the floating point number at the start of `text`, if there is one
+fn number_length(text: &[u8]) -> Option<usize> {
+    let digits = |from: usize| {
+        text[from..]
+            .iter()
+            .take_while(|byte| byte.is_ascii_digit())
+            .count()
+    };
+
+    let mut length = usize::from(matches!(text.first(), Some(b'+' | b'-')));
+
+    let integer = digits(length);
+    length += integer;
+
+    // a trailing `.` separates the number from the rest of the name
+    let fraction = match text.get(length) {
+        Some(b'.') => digits(length + 1),
+        _ => 0,
+    };
+    if fraction > 0 {
+        length += 1 + fraction;
+    }
+
+    if integer == 0 && fraction == 0 {
+        return None;
+    }
+
+    if let Some(b'e' | b'E') = text.get(length) {
+        let sign = usize::from(matches!(text.get(length + 1), Some(b'+' | b'-')));
+        let exponent = digits(length + 1 + sign);
+        if exponent > 0 {
+            length += 1 + sign + exponent;
+        }
+    }
+
+    Some(length)
+}
+
+/// convert every file of the batch, numbering the outputs next to `--output` and indexing them
+/// in a `.pvd` collection
+pub(crate) fn convert_all(args: &cli::Args, schema: &Schema) -> Result<()> {
+    let mut inputs: Vec<(Option<f64>, PathBuf)> = input_files(args)?
+        .into_iter()
+        .map(|path| {
+            let time = if args.time_from_filename {
+                Some(time_from_filename(&path)?)
+            } else {
+                None
+            };
+            Ok((time, path))
+        })
+        .collect::<Result<_>>()?;
+
+    if inputs.is_empty() {
+        bail!("no input files found for batch conversion");
+    }
+
+    // number the outputs in time order when the times are known up front, rather than in the
+    // lexical order of the file names
+    if args.time_from_filename {
+        inputs.sort_by(|(a, _), (b, _)| a.unwrap_or_default().total_cmp(&b.unwrap_or_default()));
+    }
+
+    let mut datasets = Vec::with_capacity(inputs.len());
+    let mut summaries = Vec::new();
+
+    for (index, (time, input)) in inputs.iter().enumerate() {
+        println!(
+            "converting {} ({} of {})",
+            input.display(),
+            index + 1,
+            inputs.len()
+        );
+
+        let mut dataset = crate::read_dataset(input, schema, args)?;
+        let frame_base = args.output.with_extension(dataset.layout.extension());
+        let output = writer::numbered_path(&frame_base, index);
+
+        crate::write_dataset(&output, &dataset)
+            .with_context(|| format!("failed to convert {}", input.display()))?;
+
+        // fall back to the position in the batch if no time is available
+        let time = time.or(dataset.time).unwrap_or(index as f64);
+        datasets.push((time, output));
+
+        if let Some(mut summary) = dataset.integrals.take() {
+            summary.time = Some(time);
+            summaries.push(summary);
+        }
+    }
+
+    if let Some(path) = &args.integrals_json {
+        crate::integrals::write_json(path, &summaries)?;
+    }
+
+    datasets.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+
+    let collection_path = args.output.with_extension("pvd");
+    let collection = std::fs::File::create(&collection_path).with_context(|| {
+        format!(
+            "failed to create collection file at {}",
+            collection_path.display()
+        )
+    })?;
+
+    writer::write_collection(std::io::BufWriter::new(collection), &datasets)
+        .with_context(|| format!("failed to write {}", collection_path.display()))?;
+
+    println!(
+        "wrote {} files and collection {}",
+        datasets.len(),
+        collection_path.display()
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn times_from_filenames() {
+        let time = |name: &str| time_from_filename(Path::new(name)).unwrap();
+
+        assert_eq!(time("case_t0.25.csv"), 0.25);
+        assert_eq!(time("runs/case_3_t12.csv.gz"), 12.);
+        assert_eq!(time("t-0.5.csv"), -0.5);
+        assert_eq!(time("t1e-3.csv"), 1e-3);
+        assert_eq!(time("t+2.5E2.csv"), 250.);
+        assert_eq!(time("t.5.csv"), 0.5);
+        // an `e` without exponent digits is part of the name
+        assert_eq!(time("t2_mode.csv"), 2.);
+        assert_eq!(time("t2e_x.csv"), 2.);
+
+        assert!(time_from_filename(Path::new("mode.csv")).is_err());
+    }
+
+    #[test]
+    fn time_from_filename_needs_batch_mode() {
+        use clap::Parser;
+
+        let single = ["burak-vtk", "--csv-path", "t0.5.csv", "--output", "out.vtr"];
+        assert!(cli::Args::try_parse_from(single.iter().chain(&["--time-from-filename"])).is_err());
+
+        let batch = ["burak-vtk", "--glob", "t*.csv", "--output", "out.pvd"];
+        assert!(cli::Args::try_parse_from(batch.iter().chain(&["--time-from-filename"])).is_ok());
+    }
+}
@@ -1,15 +1,287 @@
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use std::path::PathBuf;
 
 /// Burak's csv to VTK file conversion
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub(crate) struct Args {
-    /// path to .csv file to convert
-    #[arg(short, long)]
-    pub(crate) csv_path: PathBuf,
+    /// path to .csv file to convert, which may be gzip, zstd or xz compressed, a Tecplot ASCII
+    /// .dat file, a NumPy .npz archive or a MATLAB .mat file
+    #[arg(short, long, required_unless_present_any = ["glob", "list"], conflicts_with_all = ["glob", "list"])]
+    pub(crate) csv_path: Option<PathBuf>,
 
-    /// output file .vtr extension
+    /// output file .vtr extension. In batch mode this is the .pvd collection, and the converted
+    /// files are numbered alongside it
     #[arg(short, long)]
     pub(crate) output: PathBuf,
+
+    /// batch mode: convert every CSV matching this glob pattern
+    #[arg(long, conflicts_with = "list", group = "batch")]
+    pub(crate) glob: Option<String>,
+
+    /// batch mode: convert every CSV listed in this file, one path per line. Relative paths are
+    /// relative to the list file
+    #[arg(long, group = "batch")]
+    pub(crate) list: Option<PathBuf>,
+
+    /// batch mode: take the time of each file from the last number in its file name
+    #[arg(long, conflicts_with = "time_column", requires = "batch")]
+    pub(crate) time_from_filename: bool,
+
+    /// take the time of each file from this CSV column
+    #[arg(long)]
+    pub(crate) time_column: Option<String>,
+
+    /// absolute tolerance below which two coordinates are merged into one grid line
+    #[arg(long, default_value_t = 1e-12)]
+    pub(crate) abs_tol: f64,
+
+    /// relative tolerance (scaled by the coordinate magnitude) below which two coordinates are
+    /// merged into one grid line
+    #[arg(long, default_value_t = 1e-9)]
+    pub(crate) rel_tol: f64,
+
+    /// TOML or JSON file mapping CSV columns to output fields. Without a schema or any field
+    /// flags the fields are detected from the CSV header
+    #[arg(long)]
+    pub(crate) schema: Option<PathBuf>,
+
+    /// read the inputs as fortran unformatted sequential files, with the variables of each
+    /// record described by this TOML layout file
+    #[arg(long)]
+    pub(crate) fortran_layout: Option<PathBuf>,
+
+    /// comma separated names of the x, y and z coordinate columns
+    #[arg(long, value_name = "X,Y,Z")]
+    pub(crate) coordinate_columns: Option<String>,
+
+    /// coordinate system of the coordinate columns and of the components of every vector.
+    /// Cylindrical coordinates are (r, θ, z) and spherical coordinates are (r, θ, φ) with the
+    /// polar angle θ measured from the z axis, both in radians
+    #[arg(long, value_enum, default_value_t = CoordinateSystem::Cartesian)]
+    pub(crate) coordinate_system: CoordinateSystem,
+
+    /// read the points of a curvilinear grid with these index dimensions, rather than placing
+    /// them on a rectilinear grid by their coordinates. The rows must be in order of the point
+    /// index with i varying fastest
+    #[arg(long, value_name = "NI,NJ,NK", conflicts_with = "coordinate_system")]
+    pub(crate) dimensions: Option<String>,
+
+    /// write the rows as a .vtp point cloud, either when they do not form a complete
+    /// rectilinear grid (fallback) or always
+    #[arg(long, value_enum, conflicts_with = "dimensions")]
+    pub(crate) point_cloud: Option<PointCloud>,
+
+    /// real scalar field read from a single column
+    #[arg(long, value_name = "NAME=COLUMN")]
+    pub(crate) scalar: Vec<String>,
+
+    /// real vector field read from three columns
+    #[arg(long, value_name = "NAME=C1,C2,C3")]
+    pub(crate) vector: Vec<String>,
+
+    /// complex scalar field read from a real and an imaginary column
+    #[arg(long, value_name = "NAME=RE,IM")]
+    pub(crate) complex_scalar: Vec<String>,
+
+    /// complex vector field read from three real and three imaginary columns
+    #[arg(long, value_name = "NAME=R1,R2,R3:I1,I2,I3")]
+    pub(crate) complex_vector: Vec<String>,
+
+    /// expand a 2D (x, y) mode with a single z value into 3D by multiplying every complex field
+    /// by e^{iβz} along a new spanwise axis
+    #[arg(long, value_name = "BETA")]
+    pub(crate) spanwise_wavenumber: Option<f64>,
+
+    /// length of the spanwise axis built by --spanwise-wavenumber, defaults to one wavelength
+    /// 2π/β
+    #[arg(long, requires = "spanwise_wavenumber")]
+    pub(crate) spanwise_extent: Option<f64>,
+
+    /// number of points on the spanwise axis built by --spanwise-wavenumber, including both
+    /// ends
+    #[arg(long, default_value_t = 33)]
+    pub(crate) spanwise_points: usize,
+
+    /// always write a 3D rectilinear grid, rather than a polyline for line data
+    #[arg(long)]
+    pub(crate) always_3d: bool,
+
+    /// write uniformly spaced grids as .vti image data with an origin and spacing instead of
+    /// coordinate arrays
+    #[arg(long, value_enum, default_value_t = ImageData::Never)]
+    pub(crate) image_data: ImageData,
+
+    /// largest deviation of a grid line from a uniform axis, relative to the spacing, for
+    /// --image-data auto to treat the axis as uniform
+    #[arg(long, default_value_t = 1e-6)]
+    pub(crate) uniform_tol: f64,
+
+    /// instead of a single file, write this many frames of the physical field Re(f e^{iφ}) over
+    /// one period φ in [0, 2π), along with a .pvd collection for animating in paraview
+    #[arg(long, conflicts_with_all = ["glob", "list"])]
+    pub(crate) phase_frames: Option<usize>,
+
+    /// write the amplitude |f_k| and phase arg(f_k) of every component of every complex field
+    #[arg(long)]
+    pub(crate) amplitude_phase: bool,
+
+    /// unwrap the phase written by --amplitude-phase along this direction of the grid
+    #[arg(long, requires = "amplitude_phase")]
+    pub(crate) unwrap_phase: Option<Direction>,
+
+    /// rotate every complex field by a common phase so that this value of the reference field
+    /// is real and positive
+    #[arg(long)]
+    pub(crate) normalize_phase: Option<PhaseReference>,
+
+    /// scale every complex field so that this quantity of the reference field is one
+    #[arg(long)]
+    pub(crate) normalize_scale: Option<ScaleReference>,
+
+    /// complex field used as the normalisation reference, defaults to the first complex field
+    #[arg(long)]
+    pub(crate) reference_field: Option<String>,
+
+    /// component (starting at 1) of the reference field used for the phase normalisation.
+    /// Defaults to the largest component at the reference point, or the first component for
+    /// the integral
+    #[arg(long)]
+    pub(crate) reference_component: Option<usize>,
+
+    /// location used by --normalize-phase probe
+    #[arg(long, value_name = "X,Y,Z")]
+    pub(crate) probe: Option<String>,
+
+    /// name of the velocity field used for derived quantities
+    #[arg(long, default_value = "u")]
+    pub(crate) velocity_field: String,
+
+    /// name of the supplied vorticity field
+    #[arg(long, default_value = "w")]
+    pub(crate) vorticity_field: String,
+
+    /// order of accuracy (2, 4 or 6) of the finite difference derivatives
+    #[arg(long, default_value_t = 2)]
+    pub(crate) fd_order: usize,
+
+    /// compute the curl of the velocity field and report its error against the supplied
+    /// vorticity field
+    #[arg(long)]
+    pub(crate) check_vorticity: bool,
+
+    /// write the difference between the computed curl and the supplied vorticity
+    #[arg(long, requires = "check_vorticity")]
+    pub(crate) write_vorticity_error: bool,
+
+    /// write the Q-criterion and λ2 vortex identification fields of the real part of the
+    /// velocity field
+    #[arg(long)]
+    pub(crate) vortex_criteria: bool,
+
+    /// also write the Q-criterion and λ2 of the physical velocity Re(u e^{iφ}) at this phase
+    #[arg(long, value_name = "PHASE", requires = "vortex_criteria")]
+    pub(crate) vortex_phase: Option<f64>,
+
+    /// report the divergence of the real and imaginary velocity, normalised by the RMS
+    /// magnitude of the velocity gradient
+    #[arg(long)]
+    pub(crate) validate: bool,
+
+    /// warn if the normalised divergence found by --validate exceeds this value anywhere
+    #[arg(long, default_value_t = 1e-2)]
+    pub(crate) divergence_tolerance: f64,
+
+    /// write the divergence of the real and imaginary velocity found by --validate
+    #[arg(long, requires = "validate")]
+    pub(crate) write_divergence: bool,
+
+    /// write the kinetic energy density ½|u|², enstrophy density ½|w|² and helicity density
+    /// Re(u·w*) of the velocity and vorticity fields
+    #[arg(long)]
+    pub(crate) energy_densities: bool,
+
+    /// print the volume integrals of kinetic energy, enstrophy, helicity and the energy of each
+    /// velocity component, using this quadrature rule on the grid coordinates
+    #[arg(long, value_name = "RULE")]
+    pub(crate) integrals: Option<Rule>,
+
+    /// also write the integrals to this JSON file. In batch mode the file holds one entry per
+    /// input file
+    #[arg(long, requires = "integrals")]
+    pub(crate) integrals_json: Option<PathBuf>,
+}
+
+/// the value that is made real and positive by --normalize-phase
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub(crate) enum PhaseReference {
+    /// the reference component at the point where the reference field is largest
+    MaxAmplitude,
+    /// the reference component at the grid point closest to --probe
+    Probe,
+    /// the integral of the reference component over the grid
+    Integral,
+}
+
+/// the quantity made unity by --normalize-scale
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub(crate) enum ScaleReference {
+    /// the largest magnitude of the reference field
+    MaxAmplitude,
+    /// the integral of ½|f|² of the reference field over the grid
+    KineticEnergy,
+}
+
+/// coordinate system of the input, see --coordinate-system
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum CoordinateSystem {
+    Cartesian,
+    Cylindrical,
+    Spherical,
+}
+
+/// when to write a point cloud, see --point-cloud
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum PointCloud {
+    /// only if the rows do not form a complete rectilinear grid
+    Fallback,
+    Always,
+}
+
+/// when to write image data instead of a rectilinear grid
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ImageData {
+    /// always write a rectilinear grid
+    Never,
+    /// write image data if every axis is uniformly spaced
+    Auto,
+    /// write image data, and fail if an axis is not uniformly spaced
+    Always,
+}
+
+/// quadrature rule for integrals over the grid
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub(crate) enum Rule {
+    Trapezoid,
+    /// composite Simpson's rule for non-uniform spacing
+    Simpson,
+}
+
+/// a direction of the rectilinear grid
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub(crate) enum Direction {
+    X,
+    Y,
+    Z,
+}
+
+impl Direction {
+    /// position of this direction in `(x, y, z)`
+    pub(crate) fn index(&self) -> usize {
+        match self {
+            Direction::X => 0,
+            Direction::Y => 1,
+            Direction::Z => 2,
+        }
+    }
 }
@@ -0,0 +1,211 @@
+//! coordinate systems and grid geometries of the input, and the options they support
+
+use anyhow::{bail, Result};
+
+use crate::cli::{self, CoordinateSystem, PhaseReference, PointCloud, ScaleReference};
+use crate::fields::Field;
+
+impl CoordinateSystem {
+    /// the cartesian position of the point with coordinates `q` in this system
+    pub(crate) fn position(&self, [q1, q2, q3]: [f64; 3]) -> [f64; 3] {
+        match self {
+            CoordinateSystem::Cartesian => [q1, q2, q3],
+            CoordinateSystem::Cylindrical => {
+                let (sin, cos) = q2.sin_cos();
+                [q1 * cos, q1 * sin, q3]
+            }
+            CoordinateSystem::Spherical => {
+                let (sin_polar, cos_polar) = q2.sin_cos();
+                let (sin_azimuth, cos_azimuth) = q3.sin_cos();
+                [
+                    q1 * sin_polar * cos_azimuth,
+                    q1 * sin_polar * sin_azimuth,
+                    q1 * cos_polar,
+                ]
+            }
+        }
+    }
+
+    /// the cartesian components of the vector `v` given in the local basis of this system at the
+    /// point with coordinates `q`
+    pub(crate) fn rotate(&self, [_, q2, q3]: [f64; 3], [v1, v2, v3]: [f64; 3]) -> [f64; 3] {
+        match self {
+            CoordinateSystem::Cartesian => [v1, v2, v3],
+            CoordinateSystem::Cylindrical => {
+                let (sin, cos) = q2.sin_cos();
+                [v1 * cos - v2 * sin, v1 * sin + v2 * cos, v3]
+            }
+            CoordinateSystem::Spherical => {
+                let (sin_polar, cos_polar) = q2.sin_cos();
+                let (sin_azimuth, cos_azimuth) = q3.sin_cos();
+                [
+                    (v1 * sin_polar + v2 * cos_polar) * cos_azimuth - v3 * sin_azimuth,
+                    (v1 * sin_polar + v2 * cos_polar) * sin_azimuth + v3 * cos_azimuth,
+                    v1 * cos_polar - v2 * sin_polar,
+                ]
+            }
+        }
+    }
+}
+
+/// a description of the input geometry if it is not a rectilinear grid in cartesian
+/// coordinates, for error messages
+pub(crate) fn non_cartesian(args: &cli::Args) -> Option<String> {
+    if args.dimensions.is_some() {
+        Some("curvilinear grids (--dimensions)".into())
+    } else if args.point_cloud == Some(PointCloud::Always) {
+        Some("point clouds (--point-cloud always)".into())
+    } else if args.coordinate_system != CoordinateSystem::Cartesian {
+        Some(format!(
+            "--coordinate-system {}",
+            format!("{:?}", args.coordinate_system).to_lowercase()
+        ))
+    } else {
+        None
+    }
+}
+
+/// the options in use that need a rectilinear grid in cartesian coordinates. `indexed` is set if
+/// the grid lines are point indices rather than coordinates
+fn unsupported_options(args: &cli::Args, indexed: bool) -> Vec<&'static str> {
+    let unsupported = [
+        ("--validate", args.validate),
+        ("--check-vorticity", args.check_vorticity),
+        ("--vortex-criteria", args.vortex_criteria),
+        ("--integrals", args.integrals.is_some()),
+        (
+            "--normalize-phase integral",
+            matches!(args.normalize_phase, Some(PhaseReference::Integral)),
+        ),
+        (
+            "--normalize-scale kinetic-energy",
+            matches!(args.normalize_scale, Some(ScaleReference::KineticEnergy)),
+        ),
+        (
+            "--normalize-phase probe",
+            indexed && matches!(args.normalize_phase, Some(PhaseReference::Probe)),
+        ),
+        (
+            "--spanwise-wavenumber",
+            indexed && args.spanwise_wavenumber.is_some(),
+        ),
+    ];
+
+    unsupported
+        .into_iter()
+        .filter(|(_, used)| *used)
+        .map(|(option, _)| option)
+        .collect()
+}
+
+/// derivatives and integrals are computed as if the grid coordinates were cartesian, so reject
+/// the options that need them when the input is in another coordinate system, on a curvilinear
+/// grid or a point cloud
+pub(crate) fn check_supported(args: &cli::Args) -> Result<()> {
+    let Some(geometry) = non_cartesian(args) else {
+        return Ok(());
+    };
+
+    let indexed = args.dimensions.is_some() || args.point_cloud.is_some();
+    let used = unsupported_options(args, indexed);
+
+    if !used.is_empty() {
+        bail!(
+            "{} assume a rectilinear grid in cartesian coordinates and can not be used with {geometry}",
+            used.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+/// the same check as `check_supported` once the points of a file turn out to be a point cloud or
+/// a curvilinear grid, named by `geometry`. The curl of the velocity can not be used in place of
+/// a missing vorticity field either
+pub(crate) fn check_indexed(args: &cli::Args, fields: &[Field], geometry: &str) -> Result<()> {
+    let mut used = unsupported_options(args, true);
+
+    if args.energy_densities
+        && fields
+            .iter()
+            .all(|field| field.name != args.vorticity_field)
+    {
+        used.push("--energy-densities without a vorticity field");
+    }
+
+    if !used.is_empty() {
+        bail!(
+            "{} need a rectilinear grid and can not be used with {geometry}",
+            used.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::{FRAC_PI_2, FRAC_PI_4};
+
+    fn assert_close(actual: [f64; 3], expected: [f64; 3]) {
+        for (a, e) in actual.iter().zip(&expected) {
+            assert!((a - e).abs() < 1e-12, "{actual:?} != {expected:?}");
+        }
+    }
+
+    #[test]
+    fn cylindrical_basis() {
+        let system = CoordinateSystem::Cylindrical;
+        let q = [2., FRAC_PI_2, 3.];
+
+        assert_close(system.position(q), [0., 2., 3.]);
+        // e_r, e_θ and e_z at θ = π/2
+        assert_close(system.rotate(q, [1., 0., 0.]), [0., 1., 0.]);
+        assert_close(system.rotate(q, [0., 1., 0.]), [-1., 0., 0.]);
+        assert_close(system.rotate(q, [0., 0., 1.]), [0., 0., 1.]);
+    }
+
+    #[test]
+    fn spherical_basis() {
+        let system = CoordinateSystem::Spherical;
+
+        // on the equator at φ = π/2, e_r is y, e_θ points down and e_φ along -x
+        let q = [2., FRAC_PI_2, FRAC_PI_2];
+        assert_close(system.position(q), [0., 2., 0.]);
+        assert_close(system.rotate(q, [1., 0., 0.]), [0., 1., 0.]);
+        assert_close(system.rotate(q, [0., 1., 0.]), [0., 0., -1.]);
+        assert_close(system.rotate(q, [0., 0., 1.]), [-1., 0., 0.]);
+
+        // at θ = π/4, φ = 0, e_θ is tangent to the meridian in the x-z plane
+        let q = [1., FRAC_PI_4, 0.];
+        let half = FRAC_PI_4.cos();
+        assert_close(system.position(q), [half, 0., half]);
+        assert_close(system.rotate(q, [0., 1., 0.]), [half, 0., -half]);
+    }
+
+    #[test]
+    fn positions_round_trip() {
+        for q in [[1.5, 0.3, -2.], [0.5, 2.8, 0.], [3., -1.2, 1.]] {
+            let [x, y, z] = CoordinateSystem::Cylindrical.position(q);
+            assert_close([x.hypot(y), y.atan2(x), z], q);
+        }
+
+        for q in [[1.5, 0.3, -2.], [0.5, 2.8, 0.4], [3., 1.2, 3.]] {
+            let [x, y, z] = CoordinateSystem::Spherical.position(q);
+            let r = (x * x + y * y + z * z).sqrt();
+            assert_close([r, (z / r).acos(), y.atan2(x)], q);
+        }
+    }
+
+    #[test]
+    fn rotations_keep_lengths() {
+        let v = [0.3, -1.2, 2.5];
+        let length = |v: [f64; 3]| v.iter().map(|c| c * c).sum::<f64>().sqrt();
+
+        for system in [CoordinateSystem::Cylindrical, CoordinateSystem::Spherical] {
+            let rotated = system.rotate([1., 0.7, -2.1], v);
+            assert!((length(rotated) - length(v)).abs() < 1e-12);
+        }
+    }
+}
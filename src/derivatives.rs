@@ -0,0 +1,208 @@
+//! finite difference derivatives on the (possibly non-uniform) rectilinear grid
+
+use anyhow::{bail, Result};
+use ndarray::Array4;
+
+use crate::grid;
+
+/// finite difference weights for the first derivative at `x0` using the values at `nodes`
+///
+/// this is Fornberg's algorithm (Fornberg 1988, "Generation of finite difference formulas on
+/// arbitrarily spaced grids"), which handles any node spacing
+fn first_derivative_weights(x0: f64, nodes: &[f64]) -> Vec<f64> {
+    let n = nodes.len();
+    // weights[j] = [weight for the value, weight for the first derivative] of node j
+    let mut weights = vec![[0.; 2]; n];
+    weights[0][0] = 1.;
+
+    let mut c1 = 1.;
+    let mut c4 = nodes[0] - x0;
+
+    for i in 1..n {
+        let mn = i.min(1);
+        let mut c2 = 1.;
+        let c5 = c4;
+        c4 = nodes[i] - x0;
+
+        for j in 0..i {
+            let c3 = nodes[i] - nodes[j];
+            c2 *= c3;
+
+            if j == i - 1 {
+                for k in (1..=mn).rev() {
+                    weights[i][k] =
+                        c1 * (k as f64 * weights[i - 1][k - 1] - c5 * weights[i - 1][k]) / c2;
+                }
+                weights[i][0] = -c1 * c5 * weights[i - 1][0] / c2;
+            }
+
+            for k in (1..=mn).rev() {
+                weights[j][k] = (c4 * weights[j][k] - k as f64 * weights[j][k - 1]) / c3;
+            }
+            weights[j][0] = c4 * weights[j][0] / c3;
+        }
+
+        c1 = c2;
+    }
+
+    weights.into_iter().map(|weight| weight[1]).collect()
+}
+
+/// the nodes and weights used for the derivative at one grid point
+struct Stencil {
+    start: usize,
+    weights: Vec<f64>,
+}
+
+/// stencils for the derivative at every point of an axis
+fn axis_stencils(axis: &[f64], points: usize) -> Vec<Stencil> {
+    let n = axis.len();
+
+    // a single point axis is a degenerate direction, nothing varies along it
+    if n == 1 {
+        return vec![Stencil {
+            start: 0,
+            weights: Vec::new(),
+        }];
+    }
+
+    let points = points.min(n);
+
+    (0..n)
+        .map(|i| {
+            // centre the stencil on the point, shifting it inwards near the boundaries
+            let start = i.saturating_sub(points / 2).min(n - points);
+            let weights = first_derivative_weights(axis[i], &axis[start..start + points]);
+            Stencil { start, weights }
+        })
+        .collect()
+}
+
+/// first derivatives along each direction of the grid
+pub(crate) struct Differentiator {
+    stencils: [Vec<Stencil>; 3],
+}
+
+impl Differentiator {
+    /// derivatives of formal accuracy `order` (2, 4 or 6) in the interior of the grid. The
+    /// boundaries use one sided stencils with the same number of points
+    pub(crate) fn new(axes: &grid::Axes, order: usize) -> Result<Self> {
+        if !matches!(order, 2 | 4 | 6) {
+            bail!("finite difference order must be 2, 4 or 6, got {order}");
+        }
+
+        let points = order + 1;
+
+        Ok(Self {
+            stencils: [
+                axis_stencils(&axes.x.values, points),
+                axis_stencils(&axes.y.values, points),
+                axis_stencils(&axes.z.values, points),
+            ],
+        })
+    }
+
+    /// derivative of component `component` of `values` along `direction` at `point`
+    fn partial(
+        &self,
+        values: &Array4<f64>,
+        component: usize,
+        direction: usize,
+        point: [usize; 3],
+    ) -> f64 {
+        let stencil = &self.stencils[direction][point[direction]];
+        let mut index = point;
+        let mut sum = 0.;
+
+        for (offset, weight) in stencil.weights.iter().enumerate() {
+            index[direction] = stencil.start + offset;
+            sum += weight * values[[component, index[0], index[1], index[2]]];
+        }
+
+        sum
+    }
+
+    /// gradient tensor of a 3-vector field. Component `3 * a + b` of the result is
+    /// `∂u_a / ∂x_b`
+    pub(crate) fn gradient(&self, vector: &Array4<f64>) -> Array4<f64> {
+        let nx = vector.shape()[1];
+        let ny = vector.shape()[2];
+        let nz = vector.shape()[3];
+
+        let mut gradient = Array4::zeros((9, nx, ny, nz));
+
+        for i in 0..nx {
+            for j in 0..ny {
+                for k in 0..nz {
+                    for a in 0..3 {
+                        for b in 0..3 {
+                            gradient[[3 * a + b, i, j, k]] = self.partial(vector, a, b, [i, j, k]);
+                        }
+                    }
+                }
+            }
+        }
+
+        gradient
+    }
+}
+
+/// curl of a vector field from its gradient tensor
+pub(crate) fn curl(gradient: &Array4<f64>) -> Array4<f64> {
+    let nx = gradient.shape()[1];
+    let ny = gradient.shape()[2];
+    let nz = gradient.shape()[3];
+
+    let mut curl = Array4::zeros((3, nx, ny, nz));
+
+    for i in 0..nx {
+        for j in 0..ny {
+            for k in 0..nz {
+                let g = |a: usize, b: usize| gradient[[3 * a + b, i, j, k]];
+
+                curl[[0, i, j, k]] = g(2, 1) - g(1, 2);
+                curl[[1, i, j, k]] = g(0, 2) - g(2, 0);
+                curl[[2, i, j, k]] = g(1, 0) - g(0, 1);
+            }
+        }
+    }
+
+    curl
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weights_are_exact_for_polynomials_on_a_non_uniform_axis() {
+        let axis = [0., 0.1, 0.25, 0.5, 0.6, 1., 1.3, 1.35];
+
+        for points in [3, 5, 7] {
+            for (x0, stencil) in axis.iter().zip(axis_stencils(&axis, points)) {
+                let Stencil { start, weights } = stencil;
+                assert_eq!(weights.len(), points);
+                let nodes = &axis[start..start + points];
+
+                // a stencil of `points` nodes differentiates polynomials of degree below `points`
+                for power in 0..points as i32 {
+                    let derivative: f64 = nodes
+                        .iter()
+                        .zip(&weights)
+                        .map(|(x, weight)| weight * x.powi(power))
+                        .sum();
+                    let exact = if power == 0 {
+                        0.
+                    } else {
+                        power as f64 * x0.powi(power - 1)
+                    };
+
+                    assert!(
+                        (derivative - exact).abs() < 1e-9,
+                        "{points} points at {x0}, x^{power}: {derivative} != {exact}"
+                    );
+                }
+            }
+        }
+    }
+}
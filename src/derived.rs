@@ -0,0 +1,183 @@
+//! quantities computed point by point from the fields read from the input
+
+use ndarray::Array4;
+
+use crate::cli::Direction;
+use crate::fields::Field;
+use crate::writer::PointArray;
+
+/// amplitude `|f_k|` and phase `arg(f_k)` of every component of every complex field, written as
+/// `amplitude_<name><k>` and `phase_<name><k>` (without the component number for scalars)
+///
+/// if `unwrap` is given, the phase is made continuous along that direction of the grid
+pub(crate) fn amplitude_phase(fields: &[Field], unwrap: Option<Direction>) -> Vec<PointArray<'static>> {
+    let mut arrays = Vec::new();
+
+    for field in fields {
+        let Some(imaginary) = &field.imaginary else {
+            continue;
+        };
+
+        let nx = field.real.shape()[1];
+        let ny = field.real.shape()[2];
+        let nz = field.real.shape()[3];
+
+        for component in 0..field.components() {
+            let mut amplitude = Array4::zeros((1, nx, ny, nz));
+            let mut phase = Array4::zeros((1, nx, ny, nz));
+
+            for i in 0..nx {
+                for j in 0..ny {
+                    for k in 0..nz {
+                        let re = field.real[[component, i, j, k]];
+                        let im = imaginary[[component, i, j, k]];
+
+                        amplitude[[0, i, j, k]] = re.hypot(im);
+                        phase[[0, i, j, k]] = im.atan2(re);
+                    }
+                }
+            }
+
+            if let Some(direction) = unwrap {
+                unwrap_phase(&mut phase, direction);
+            }
+
+            let suffix = if field.components() == 1 {
+                String::new()
+            } else {
+                (component + 1).to_string()
+            };
+
+            arrays.push(PointArray::owned(
+                format!("amplitude_{}{suffix}", field.name),
+                amplitude,
+            ));
+            arrays.push(PointArray::owned(
+                format!("phase_{}{suffix}", field.name),
+                phase,
+            ));
+        }
+    }
+
+    arrays
+}
+
+/// remove the 2π jumps of a phase in `(-π, π]` along every grid line in `direction`
+fn unwrap_phase(phase: &mut Array4<f64>, direction: Direction) {
+    use std::f64::consts::PI;
+
+    let shape = [phase.shape()[1], phase.shape()[2], phase.shape()[3]];
+    let along = direction.index();
+    // the two directions that enumerate the grid lines
+    let [first, second] = match along {
+        0 => [1, 2],
+        1 => [0, 2],
+        _ => [0, 1],
+    };
+
+    for a in 0..shape[first] {
+        for b in 0..shape[second] {
+            let index = |n: usize| {
+                let mut index = [0; 3];
+                index[first] = a;
+                index[second] = b;
+                index[along] = n;
+                [0, index[0], index[1], index[2]]
+            };
+
+            let mut previous_raw = phase[index(0)];
+            let mut previous = previous_raw;
+
+            for n in 1..shape[along] {
+                let raw = phase[index(n)];
+
+                // the jump between neighbours, wrapped into [-π, π)
+                let jump = (raw - previous_raw + PI).rem_euclid(2. * PI) - PI;
+                let unwrapped = previous + jump;
+
+                phase[index(n)] = unwrapped;
+                previous_raw = raw;
+                previous = unwrapped;
+            }
+        }
+    }
+}
+
+/// kinetic energy density `½|u|²`, enstrophy density `½|w|²` and helicity density `Re(u·w*)`
+/// of a complex velocity and vorticity
+pub(crate) fn energy_densities(velocity: &Field, vorticity: &Field) -> Vec<PointArray<'static>> {
+    let nx = velocity.real.shape()[1];
+    let ny = velocity.real.shape()[2];
+    let nz = velocity.real.shape()[3];
+
+    let mut kinetic_energy = Array4::zeros((1, nx, ny, nz));
+    let mut enstrophy = Array4::zeros((1, nx, ny, nz));
+    let mut helicity = Array4::zeros((1, nx, ny, nz));
+
+    for i in 0..nx {
+        for j in 0..ny {
+            for k in 0..nz {
+                let mut u_squared = 0.;
+                let mut w_squared = 0.;
+                let mut u_dot_w = 0.;
+
+                for v in 0..3 {
+                    let ur = velocity.real[[v, i, j, k]];
+                    let wr = vorticity.real[[v, i, j, k]];
+                    // a real field has no imaginary part
+                    let ui = velocity.imaginary.as_ref().map_or(0., |im| im[[v, i, j, k]]);
+                    let wi = vorticity.imaginary.as_ref().map_or(0., |im| im[[v, i, j, k]]);
+
+                    u_squared += ur * ur + ui * ui;
+                    w_squared += wr * wr + wi * wi;
+                    // Re((ur + i ui) (wr - i wi))
+                    u_dot_w += ur * wr + ui * wi;
+                }
+
+                kinetic_energy[[0, i, j, k]] = 0.5 * u_squared;
+                enstrophy[[0, i, j, k]] = 0.5 * w_squared;
+                helicity[[0, i, j, k]] = u_dot_w;
+            }
+        }
+    }
+
+    vec![
+        PointArray::owned("kinetic_energy_density", kinetic_energy),
+        PointArray::owned("enstrophy_density", enstrophy),
+        PointArray::owned("helicity_density", helicity),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    /// wrap an angle into [-π, π)
+    fn wrap(angle: f64) -> f64 {
+        (angle + PI).rem_euclid(2. * PI) - PI
+    }
+
+    #[test]
+    fn unwrap_across_the_branch_cut() {
+        // along y, one line of phases increasing through +π and one decreasing through -π
+        let steps = [0.8, -0.9];
+        let start = [2.5, -2.5];
+        let mut phase = Array4::from_shape_fn((1, 2, 8, 1), |(_, i, j, _)| {
+            wrap(start[i] + steps[i] * j as f64)
+        });
+
+        unwrap_phase(&mut phase, Direction::Y);
+
+        for i in 0..2 {
+            for j in 0..8 {
+                let expected = start[i] + steps[i] * j as f64;
+                assert!(
+                    (phase[[0, i, j, 0]] - expected).abs() < 1e-12,
+                    "{:?}",
+                    phase
+                );
+            }
+        }
+    }
+}
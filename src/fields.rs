@@ -0,0 +1,118 @@
+//! real and complex valued fields on the grid
+
+use anyhow::{bail, Context, Result};
+use ndarray::Array4;
+
+use crate::schema;
+use crate::writer::PointArray;
+
+/// a scalar or vector field with shape `(components, nx, ny, nz)`, complex if it has an
+/// imaginary part
+#[derive(Clone)]
+pub(crate) struct Field {
+    pub(crate) name: String,
+    pub(crate) real: Array4<f64>,
+    pub(crate) imaginary: Option<Array4<f64>>,
+}
+
+impl Field {
+    pub(crate) fn components(&self) -> usize {
+        self.real.shape()[0]
+    }
+
+    /// the arrays written to the output for this field: complex fields are written as their
+    /// real part, imaginary part and total magnitude
+    pub(crate) fn point_arrays(&self) -> Vec<PointArray<'_>> {
+        match &self.imaginary {
+            Some(imaginary) => vec![
+                PointArray::borrowed(format!("real_{}", self.name), &self.real),
+                PointArray::borrowed(format!("imaginary_{}", self.name), imaginary),
+                PointArray::owned(
+                    format!("total_{}_magnitude", self.name),
+                    magnitude_complex(&self.real, Some(imaginary)),
+                ),
+            ],
+            None => vec![PointArray::borrowed(self.name.clone(), &self.real)],
+        }
+    }
+
+    /// the physical field `Re(f e^{iφ})` at phase `phase`, or the field itself if it is real
+    pub(crate) fn at_phase(&self, phase: f64) -> PointArray<'_> {
+        let Some(imaginary) = &self.imaginary else {
+            return PointArray::borrowed(self.name.clone(), &self.real);
+        };
+
+        let (sin, cos) = phase.sin_cos();
+        let mut physical = self.real.clone();
+
+        for (value, im) in physical.iter_mut().zip(imaginary.iter()) {
+            *value = *value * cos - im * sin;
+        }
+
+        PointArray::owned(self.name.clone(), physical)
+    }
+}
+
+/// magnitude of a (possibly complex) scalar or vector at every point of the grid
+pub(crate) fn magnitude_complex(real: &Array4<f64>, im: Option<&Array4<f64>>) -> Array4<f64> {
+    let components = real.shape()[0];
+    let nx = real.shape()[1];
+    let ny = real.shape()[2];
+    let nz = real.shape()[3];
+
+    let mut out = Array4::zeros((1, nx, ny, nz));
+
+    for i in 0..nx {
+        for j in 0..ny {
+            for k in 0..nz {
+                let mut magnitude_squared = 0.;
+
+                // the magnitude of a vector of complex numbers is the sum of the squares of all
+                // components
+                for v in 0..components {
+                    magnitude_squared += real[[v, i, j, k]].powi(2);
+                    if let Some(im) = im {
+                        magnitude_squared += im[[v, i, j, k]].powi(2);
+                    }
+                }
+
+                out[[0, i, j, k]] = magnitude_squared.sqrt();
+            }
+        }
+    }
+
+    out
+}
+
+/// the field called `name`
+pub(crate) fn find<'a>(fields: &'a [Field], name: &str) -> Result<&'a Field> {
+    fields
+        .iter()
+        .find(|field| field.name == name)
+        // the velocity of the legacy CSV layout keeps its old name, but is still the default `u`
+        .or_else(|| match name {
+            "u" => fields
+                .iter()
+                .find(|field| field.name == schema::LEGACY_VELOCITY),
+            _ => None,
+        })
+        .with_context(|| {
+            let available = fields
+                .iter()
+                .map(|field| field.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("field `{name}` does not exist (available fields: {available})")
+        })
+}
+
+/// the field called `name`, which must be a 3-vector
+pub(crate) fn find_vector<'a>(fields: &'a [Field], name: &str) -> Result<&'a Field> {
+    let field = find(fields, name)?;
+
+    if field.components() != 3 {
+        bail!("field `{name}` is a scalar, a vector field is required");
+    }
+
+    Ok(field)
+}
@@ -0,0 +1,545 @@
+//! reader for fortran unformatted sequential files, described by a TOML layout file
+//!
+//! every `write` statement of a sequential unformatted file produces one record, surrounded by
+//! leading and trailing markers holding its length in bytes. The layout lists the variables of
+//! each record in order, and the variables are then mapped onto the grid by the schema like the
+//! arrays of an npz archive
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::arrays::{self, Array, Variables};
+use crate::grid;
+use crate::npy::Dtype;
+use crate::schema::Schema;
+use crate::RawDataset;
+
+/// the records of a file, in the order they were written
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Layout {
+    /// size of the record markers in bytes, 4 or 8. Detected from the first record if omitted
+    #[serde(default)]
+    marker: Option<usize>,
+    /// byte order of the markers and values. Detected from the first record if omitted
+    #[serde(default)]
+    byte_order: Option<ByteOrder>,
+    #[serde(default, rename = "record")]
+    records: Vec<Record>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum ByteOrder {
+    Little,
+    Big,
+}
+
+/// one record of the file. A record without variables is skipped
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Record {
+    #[serde(default)]
+    variables: Vec<Variable>,
+}
+
+/// a scalar or array written to a record
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Variable {
+    #[serde(default)]
+    name: Option<String>,
+    /// several arrays of the same type and shape written one after another, such as the blocks
+    /// of an eigenvector `q(nx, ny, nz, 4)` holding `u_1, u_2, u_3, p`
+    #[serde(default)]
+    names: Vec<String>,
+    #[serde(rename = "type")]
+    kind: Kind,
+    /// bytes per value, defaults to 4 for integers, 8 for reals and 16 for complex numbers
+    #[serde(default)]
+    bytes: Option<usize>,
+    /// the fortran dimensions of the array, either lengths or the names of integers read from an
+    /// earlier record. A scalar if empty
+    #[serde(default)]
+    shape: Vec<Dimension>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum Kind {
+    Integer,
+    Real,
+    Complex,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum Dimension {
+    Length(usize),
+    Variable(String),
+}
+
+impl Layout {
+    fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read fortran layout file at {}", path.display()))?;
+
+        toml::from_str(&contents)
+            .with_context(|| format!("failed to parse TOML fortran layout {}", path.display()))
+    }
+}
+
+/// reads the records of a sequential unformatted file one at a time
+struct Records<R> {
+    reader: R,
+    marker: usize,
+    byte_order: ByteOrder,
+    /// length of the file in bytes, which no record can exceed
+    length: u64,
+}
+
+impl<R: Read + Seek> Records<R> {
+    /// detect the size and byte order of the record markers from the first record, by finding
+    /// the combination whose trailing marker matches the leading one
+    fn detect(mut reader: R, marker: Option<usize>, byte_order: Option<ByteOrder>) -> Result<Self> {
+        let length = reader.seek(SeekFrom::End(0))?;
+
+        for size in [4, 8] {
+            for order in [ByteOrder::Little, ByteOrder::Big] {
+                if marker.is_some_and(|marker| marker != size)
+                    || byte_order.is_some_and(|byte_order| byte_order != order)
+                {
+                    continue;
+                }
+
+                let mut records = Self {
+                    reader,
+                    marker: size,
+                    byte_order: order,
+                    length,
+                };
+
+                let matches = records.first_record_matches(length);
+                reader = records.reader;
+
+                if matches? {
+                    reader.seek(SeekFrom::Start(0))?;
+                    return Ok(Self {
+                        reader,
+                        marker: size,
+                        byte_order: order,
+                        length,
+                    });
+                }
+            }
+        }
+
+        bail!("could not find consistent record markers, the file may not be a fortran unformatted sequential file");
+    }
+
+    fn first_record_matches(&mut self, file_length: u64) -> Result<bool> {
+        self.reader.seek(SeekFrom::Start(0))?;
+        let Some(head) = self.read_marker()? else {
+            return Ok(false);
+        };
+
+        let bytes = head.unsigned_abs();
+        if bytes + 2 * self.marker as u64 > file_length {
+            return Ok(false);
+        }
+
+        self.reader.seek(SeekFrom::Start(self.marker as u64 + bytes))?;
+        Ok(self.read_marker()?.map(i64::unsigned_abs) == Some(bytes))
+    }
+
+    /// the next record marker, or `None` at the end of the file
+    fn read_marker(&mut self) -> Result<Option<i64>> {
+        let mut buffer = [0; 8];
+        let bytes = &mut buffer[..self.marker];
+
+        match self.reader.read_exact(bytes) {
+            Ok(()) => {}
+            Err(error) if error.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(error) => return Err(error.into()),
+        }
+
+        if self.byte_order == ByteOrder::Big {
+            bytes.reverse();
+        }
+
+        let value = match self.marker {
+            4 => i32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as i64,
+            _ => i64::from_le_bytes(buffer),
+        };
+
+        Ok(Some(value))
+    }
+
+    /// the contents of the next record, or `None` at the end of the file. Records longer than
+    /// the marker allows are split into subrecords with negative markers, which are joined
+    fn next(&mut self) -> Result<Option<Vec<u8>>> {
+        let mut contents = Vec::new();
+
+        loop {
+            let Some(head) = self.read_marker()? else {
+                if contents.is_empty() {
+                    return Ok(None);
+                }
+                bail!("file ended inside a record");
+            };
+
+            // check the length against the rest of the file before allocating, so a corrupt
+            // marker is an error rather than a huge allocation
+            let position = self.reader.stream_position()?;
+            let bytes = head.unsigned_abs();
+            if bytes + self.marker as u64 > self.length.saturating_sub(position) {
+                bail!(
+                    "record of {bytes} bytes at byte {position} runs past the end of the file ({} bytes)",
+                    self.length
+                );
+            }
+
+            let start = contents.len();
+            contents.resize(start + bytes as usize, 0);
+            self.reader
+                .read_exact(&mut contents[start..])
+                .with_context(|| "file ended inside a record")?;
+
+            let tail = self.read_marker()?.unwrap_or_default();
+            if tail.unsigned_abs() != head.unsigned_abs() {
+                bail!("record markers do not match ({head} at the start and {tail} at the end of a record)");
+            }
+
+            // a negative leading marker means the record continues in another subrecord
+            if head >= 0 {
+                return Ok(Some(contents));
+            }
+        }
+    }
+}
+
+/// read every variable of the layout from the records of `reader`
+fn read_variables<R: Read + Seek>(reader: R, layout: &Layout, path: &Path) -> Result<Variables> {
+    let mut records = Records::detect(reader, layout.marker, layout.byte_order)?;
+    let order = match records.byte_order {
+        ByteOrder::Little => '<',
+        ByteOrder::Big => '>',
+    };
+
+    let mut variables = Variables::new(format!("the variables of {}", path.display()));
+    // integer scalars, which may give the dimensions of later arrays
+    let mut integers: HashMap<String, usize> = HashMap::new();
+
+    for (index, record) in layout.records.iter().enumerate() {
+        let number = index + 1;
+        let contents = records
+            .next()?
+            .with_context(|| format!("file ended before record {number} of the layout"))?;
+
+        if record.variables.is_empty() {
+            continue;
+        }
+
+        let mut offset = 0;
+
+        for variable in &record.variables {
+            let names: Vec<&String> = variable.name.iter().chain(&variable.names).collect();
+            if names.is_empty() {
+                bail!("a variable of record {number} of the layout has no name");
+            }
+
+            let shape = variable
+                .shape
+                .iter()
+                .map(|dimension| match dimension {
+                    Dimension::Length(length) => Ok(*length),
+                    Dimension::Variable(name) => integers.get(name).copied().with_context(|| {
+                        format!("dimension `{name}` of record {number} is not an integer read from an earlier record")
+                    }),
+                })
+                .collect::<Result<Vec<usize>>>()?;
+
+            let (kind, default_bytes) = match variable.kind {
+                Kind::Integer => ('i', 4),
+                Kind::Real => ('f', 8),
+                Kind::Complex => ('c', 16),
+            };
+            let dtype = Dtype::parse(&format!(
+                "{order}{kind}{}",
+                variable.bytes.unwrap_or(default_bytes)
+            ))?;
+
+            let count: usize = shape.iter().product();
+            let block = count * dtype.size();
+
+            for name in names {
+                let Some(data) = contents.get(offset..offset + block) else {
+                    bail!(
+                        "record {number} holds {} bytes, which is too short for variable `{name}` of the layout",
+                        contents.len()
+                    );
+                };
+                offset += block;
+
+                let (real, imaginary) = dtype.decode(data);
+
+                if let (Kind::Integer, [value]) = (variable.kind, &real[..]) {
+                    integers.insert(name.clone(), *value as usize);
+                }
+
+                variables.insert(
+                    name.clone(),
+                    Array::from_column_major(shape.clone(), real, imaginary),
+                );
+            }
+        }
+
+        if offset != contents.len() {
+            bail!(
+                "record {number} holds {} bytes but its variables in the layout take {offset} bytes",
+                contents.len()
+            );
+        }
+    }
+
+    Ok(variables)
+}
+
+/// read the grid and every field of the schema from a fortran unformatted sequential file
+pub(crate) fn read(
+    path: &Path,
+    layout: &Path,
+    schema: Schema,
+    tolerance: grid::Tolerance,
+) -> Result<RawDataset> {
+    let layout = Layout::load(layout)?;
+
+    let file = std::fs::File::open(path)
+        .with_context(|| format!("failed to open fortran file at {}", path.display()))?;
+
+    let mut variables = read_variables(BufReader::new(file), &layout, path)
+        .with_context(|| format!("failed to read fortran file {}", path.display()))?;
+
+    arrays::read(&mut variables, schema, tolerance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arrays::Arrays;
+    use std::io::Cursor;
+
+    /// the bytes of a file written with `marker` byte markers in `order`
+    struct File {
+        bytes: Vec<u8>,
+        marker: usize,
+        order: ByteOrder,
+    }
+
+    impl File {
+        fn new(marker: usize, order: ByteOrder) -> Self {
+            Self {
+                bytes: Vec::new(),
+                marker,
+                order,
+            }
+        }
+
+        fn marker(&mut self, length: i64) {
+            let bytes = match (self.marker, self.order) {
+                (4, ByteOrder::Little) => (length as i32).to_le_bytes().to_vec(),
+                (4, ByteOrder::Big) => (length as i32).to_be_bytes().to_vec(),
+                (_, ByteOrder::Little) => length.to_le_bytes().to_vec(),
+                (_, ByteOrder::Big) => length.to_be_bytes().to_vec(),
+            };
+            self.bytes.extend(bytes);
+        }
+
+        fn record(&mut self, contents: &[u8]) {
+            self.marker(contents.len() as i64);
+            self.bytes.extend(contents);
+            self.marker(contents.len() as i64);
+        }
+
+        /// a record split into two subrecords, as gfortran writes records over 2 GiB
+        fn split_record(&mut self, contents: &[u8], at: usize) {
+            self.marker(-(at as i64));
+            self.bytes.extend(&contents[..at]);
+            self.marker(-(at as i64));
+            self.record(&contents[at..]);
+        }
+
+        fn integers(&self, values: &[i32]) -> Vec<u8> {
+            values
+                .iter()
+                .flat_map(|value| match self.order {
+                    ByteOrder::Little => value.to_le_bytes(),
+                    ByteOrder::Big => value.to_be_bytes(),
+                })
+                .collect()
+        }
+
+        fn reals(&self, values: &[f64]) -> Vec<u8> {
+            values
+                .iter()
+                .flat_map(|value| match self.order {
+                    ByteOrder::Little => value.to_le_bytes(),
+                    ByteOrder::Big => value.to_be_bytes(),
+                })
+                .collect()
+        }
+    }
+
+    fn variable(names: &[&str], kind: Kind, shape: &[&str]) -> Variable {
+        Variable {
+            name: None,
+            names: names.iter().map(|name| name.to_string()).collect(),
+            kind,
+            bytes: None,
+            shape: shape
+                .iter()
+                .map(|dimension| Dimension::Variable(dimension.to_string()))
+                .collect(),
+        }
+    }
+
+    /// the dimensions, a skipped record, the axes and a complex vector
+    fn layout() -> Layout {
+        Layout {
+            marker: None,
+            byte_order: None,
+            records: vec![
+                Record {
+                    variables: vec![variable(&["nx", "ny", "nz"], Kind::Integer, &[])],
+                },
+                Record { variables: vec![] },
+                Record {
+                    variables: vec![
+                        variable(&["x"], Kind::Real, &["nx"]),
+                        variable(&["y"], Kind::Real, &["ny"]),
+                        Variable {
+                            shape: vec![Dimension::Length(2)],
+                            ..variable(&["z"], Kind::Real, &[])
+                        },
+                    ],
+                },
+                Record {
+                    variables: vec![variable(
+                        &["u1", "u2", "u3"],
+                        Kind::Complex,
+                        &["nx", "ny", "nz"],
+                    )],
+                },
+            ],
+        }
+    }
+
+    /// a file for `layout()` on a 3x2x2 grid, where component `c` of the velocity at `(i, j, k)`
+    /// is `1000 c + 100 i + 10 j + k` with the negated imaginary part
+    fn file(marker: usize, order: ByteOrder, split: bool) -> Vec<u8> {
+        let mut file = File::new(marker, order);
+
+        let dimensions = file.integers(&[3, 2, 2]);
+        file.record(&dimensions);
+        file.record(b"skipped!");
+        let axes = file.reals(&[0., 1., 2., 0., 1., 0., 2.]);
+        file.record(&axes);
+
+        let mut velocity = Vec::new();
+        for c in 0..3 {
+            for k in 0..2 {
+                for j in 0..2 {
+                    for i in 0..3 {
+                        let value = (1000 * c + 100 * i + 10 * j + k) as f64;
+                        velocity.extend([value, -value]);
+                    }
+                }
+            }
+        }
+        let velocity = file.reals(&velocity);
+
+        if split {
+            file.split_record(&velocity, 48);
+        } else {
+            file.record(&velocity);
+        }
+
+        file.bytes
+    }
+
+    fn read(bytes: Vec<u8>, layout: &Layout) -> Result<Variables> {
+        read_variables(Cursor::new(bytes), layout, Path::new("mode.bin"))
+    }
+
+    #[test]
+    fn markers_and_byte_orders_are_detected() {
+        for marker in [4, 8] {
+            for order in [ByteOrder::Little, ByteOrder::Big] {
+                for split in [false, true] {
+                    let mut variables = read(file(marker, order, split), &layout()).unwrap();
+                    assert_eq!(variables.names(), ["x", "y", "z", "u1", "u2", "u3"]);
+
+                    let u3 = variables.array("u3").unwrap();
+                    assert_eq!(u3.shape, [3, 2, 2]);
+                    // row major index of (2, 1, 1)
+                    assert_eq!(u3.real[2 * 4 + 2 + 1], 2211.);
+                    assert_eq!(u3.imaginary.unwrap()[2 * 4 + 2 + 1], -2211.);
+                    assert_eq!(variables.array("z").unwrap().real, [0., 2.]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn given_markers_must_match() {
+        let mut layout = layout();
+        layout.marker = Some(8);
+
+        let error = read(file(4, ByteOrder::Little, false), &layout)
+            .err()
+            .unwrap();
+        assert!(
+            error.to_string().contains("consistent record markers"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn variables_can_be_used_by_several_fields() {
+        let mut variables = read(file(4, ByteOrder::Little, false), &layout()).unwrap();
+
+        let first = variables.array("x").unwrap();
+        let second = variables.array("x").unwrap();
+        assert_eq!(first.real, second.real);
+    }
+
+    #[test]
+    fn corrupt_markers_are_rejected_before_allocating() {
+        let mut bytes = file(4, ByteOrder::Little, false);
+        // the leading marker of the skipped record, after the 12 byte dimension record
+        bytes[20..24].copy_from_slice(&i32::MAX.to_le_bytes());
+
+        let error = read(bytes, &layout()).err().unwrap();
+        assert!(
+            error.to_string().contains("runs past the end of the file"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn records_must_hold_exactly_their_variables() {
+        let mut layout = layout();
+        layout.records[0].variables = vec![variable(&["nx", "ny"], Kind::Integer, &[])];
+
+        let error = read(file(4, ByteOrder::Little, false), &layout)
+            .err()
+            .unwrap();
+        assert!(
+            error.to_string().contains("record 1 holds 12 bytes"),
+            "{error}"
+        );
+    }
+}
@@ -0,0 +1,409 @@
+//! rectilinear grid axes recovered from scattered point coordinates
+
+/// absolute and relative tolerance used to decide if two coordinates are the same grid line
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Tolerance {
+    pub(crate) absolute: f64,
+    pub(crate) relative: f64,
+}
+
+impl Default for Tolerance {
+    /// the defaults of `--abs-tol` and `--rel-tol`
+    fn default() -> Self {
+        Self {
+            absolute: 1e-12,
+            relative: 1e-9,
+        }
+    }
+}
+
+impl Tolerance {
+    /// largest distance between `a` and `b` for them to be considered the same coordinate
+    fn allowed(&self, a: f64, b: f64) -> f64 {
+        self.absolute.max(self.relative * a.abs().max(b.abs()))
+    }
+
+    pub(crate) fn same(&self, a: f64, b: f64) -> bool {
+        (a - b).abs() <= self.allowed(a, b)
+    }
+}
+
+/// the distinct, monotonically increasing coordinates along one direction of the grid
+pub(crate) struct Axis {
+    /// representative coordinate of each grid line (the median of all values merged into it)
+    pub(crate) values: Vec<f64>,
+    /// smallest and largest raw coordinate that was merged into each grid line
+    bounds: Vec<(f64, f64)>,
+    /// number of distinct raw coordinates that were folded into a neighbouring grid line
+    pub(crate) merged: usize,
+    tolerance: Tolerance,
+}
+
+impl Axis {
+    /// build an axis from every coordinate seen along one direction. `raw` may contain
+    /// duplicates and be in any order
+    pub(crate) fn from_values(mut raw: Vec<f64>, tolerance: Tolerance) -> Self {
+        raw.sort_unstable_by(f64::total_cmp);
+        raw.dedup();
+
+        let mut values = Vec::new();
+        let mut bounds = Vec::new();
+
+        let mut start = 0;
+        while start < raw.len() {
+            // compare against the first value of the group so that a slow drift of
+            // coordinates can not chain many grid lines together
+            let length = raw[start..]
+                .iter()
+                .take_while(|value| tolerance.same(raw[start], **value))
+                .count();
+            let group = &raw[start..start + length];
+
+            values.push(group[group.len() / 2]);
+            bounds.push((group[0], group[group.len() - 1]));
+
+            start += length;
+        }
+
+        let merged = raw.len() - values.len();
+
+        Self {
+            values,
+            bounds,
+            merged,
+            tolerance,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// the spacing of the axis if every grid line is within `relative` times the spacing of its
+    /// position on a uniform axis. A single point has unit spacing
+    pub(crate) fn uniform_spacing(&self, relative: f64) -> Option<f64> {
+        let [first, .., last] = self.values[..] else {
+            return Some(1.);
+        };
+
+        let spacing = (last - first) / (self.len() - 1) as f64;
+
+        self.values
+            .iter()
+            .enumerate()
+            .all(|(index, value)| (value - (first + index as f64 * spacing)).abs() <= relative * spacing)
+            .then_some(spacing)
+    }
+
+    /// index of the grid line that `value` belongs to, if any
+    pub(crate) fn index_of(&self, value: f64) -> Option<usize> {
+        // first grid line whose group lies entirely above `value`
+        let above = self.bounds.partition_point(|(low, _)| *low <= value);
+
+        if above > 0 && value <= self.bounds[above - 1].1 {
+            return Some(above - 1);
+        }
+
+        // the value was not part of the initial scan, fall back to the nearest grid line
+        [above.checked_sub(1), Some(above)]
+            .into_iter()
+            .flatten()
+            .filter(|idx| *idx < self.values.len())
+            .filter(|idx| self.tolerance.same(self.values[*idx], value))
+            .min_by(|a, b| {
+                let da = (self.values[*a] - value).abs();
+                let db = (self.values[*b] - value).abs();
+                da.total_cmp(&db)
+            })
+    }
+}
+
+/// collects the distinct coordinates along one direction while streaming through the rows of
+/// a file
+///
+/// repeated values are compacted away periodically, so the memory and time used scale with the
+/// number of grid lines rather than the number of rows
+pub(crate) struct AxisCollector {
+    values: Vec<f64>,
+    last: Option<f64>,
+    compact_at: usize,
+}
+
+impl AxisCollector {
+    const MIN_COMPACT: usize = 4096;
+
+    pub(crate) fn new() -> Self {
+        Self {
+            values: Vec::new(),
+            last: None,
+            compact_at: Self::MIN_COMPACT,
+        }
+    }
+
+    pub(crate) fn push(&mut self, value: f64) {
+        // the slower varying directions repeat the same value for long runs of rows
+        if self.last == Some(value) {
+            return;
+        }
+
+        self.last = Some(value);
+        self.values.push(value);
+
+        if self.values.len() >= self.compact_at {
+            self.values.sort_unstable_by(f64::total_cmp);
+            self.values.dedup();
+            self.compact_at = (2 * self.values.len()).max(Self::MIN_COMPACT);
+        }
+    }
+
+    pub(crate) fn finish(self, tolerance: Tolerance) -> Axis {
+        Axis::from_values(self.values, tolerance)
+    }
+}
+
+/// the three axes of a rectilinear grid
+pub(crate) struct Axes {
+    pub(crate) x: Axis,
+    pub(crate) y: Axis,
+    pub(crate) z: Axis,
+}
+
+impl Axes {
+    /// axes holding the point indices `0, 1, ...` of a curvilinear grid with `lengths` points
+    /// along each direction
+    pub(crate) fn indices(lengths: [usize; 3], tolerance: Tolerance) -> Self {
+        let [x, y, z] = lengths
+            .map(|length| Axis::from_values((0..length).map(|index| index as f64).collect(), tolerance));
+
+        Self { x, y, z }
+    }
+
+    /// find the (i,j,k) index of a point on the grid, if it lies on the grid
+    pub(crate) fn locate(&self, x: f64, y: f64, z: f64) -> Option<[usize; 3]> {
+        let i = self.x.index_of(x)?;
+        let j = self.y.index_of(y)?;
+        let k = self.z.index_of(z)?;
+        Some([i, j, k])
+    }
+
+    /// print how many near-duplicate coordinates were merged along each direction
+    pub(crate) fn report_merges(&self) {
+        let merged = [self.x.merged, self.y.merged, self.z.merged];
+
+        if merged.iter().all(|count| *count == 0) {
+            return;
+        }
+
+        println!(
+            "merged near-duplicate coordinates within tolerance: x: {}, y: {}, z: {}",
+            merged[0], merged[1], merged[2]
+        );
+    }
+}
+
+/// every nesting of the three loops (slowest to fastest varying axis) that a CSV may have
+/// been written with
+const ORDERINGS: [[usize; 3]; 6] = [
+    [0, 1, 2],
+    [0, 2, 1],
+    [1, 0, 2],
+    [1, 2, 0],
+    [2, 0, 1],
+    [2, 1, 0],
+];
+
+const AXIS_NAMES: [&str; 3] = ["x", "y", "z"];
+
+/// tracks which loop orderings are consistent with the rows of the CSV seen so far
+///
+/// this is only used for diagnostics - rows are always placed on the grid by their coordinates
+pub(crate) struct OrderingDetector {
+    lengths: [usize; 3],
+    candidates: [bool; 6],
+}
+
+impl OrderingDetector {
+    pub(crate) fn new(nx: usize, ny: usize, nz: usize) -> Self {
+        Self {
+            lengths: [nx, ny, nz],
+            candidates: [true; 6],
+        }
+    }
+
+    /// record that the `row_number`-th data row (zero indexed) was found at `index`
+    pub(crate) fn observe(&mut self, row_number: usize, index: [usize; 3]) {
+        for (candidate, ordering) in self.candidates.iter_mut().zip(ORDERINGS.iter()) {
+            if !*candidate {
+                continue;
+            }
+
+            let mut linear = 0;
+            for axis in ordering {
+                linear = linear * self.lengths[*axis] + index[*axis];
+            }
+
+            if linear != row_number {
+                *candidate = false;
+            }
+        }
+    }
+
+    /// human readable description of the row ordering of the file
+    pub(crate) fn describe(&self) -> String {
+        let Some((_, ordering)) = self
+            .candidates
+            .iter()
+            .zip(ORDERINGS.iter())
+            .find(|(candidate, _)| **candidate)
+        else {
+            return "no consistent loop order, rows placed by coordinate".into();
+        };
+
+        // axes with a single point do not contribute to the ordering
+        let varying: Vec<&str> = ordering
+            .iter()
+            .filter(|axis| self.lengths[**axis] > 1)
+            .map(|axis| AXIS_NAMES[*axis])
+            .collect();
+
+        match varying.as_slice() {
+            [] => "single point".into(),
+            [only] => format!("only {only} varies"),
+            [slowest, .., fastest] => format!("{slowest} slowest, {fastest} fastest"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn near_duplicates_merge() {
+        // within the absolute tolerance near zero and the relative tolerance near one
+        let raw = vec![1. + 5e-10, 0., 2., 1e-13, 1., 0., 2.];
+        let axis = Axis::from_values(raw, Tolerance::default());
+
+        assert_eq!(axis.len(), 3);
+        assert_eq!(axis.merged, 2);
+        assert_eq!(axis.values[2], 2.);
+    }
+
+    #[test]
+    fn values_outside_tolerance_stay_separate() {
+        let raw = vec![0., 2e-12, 1., 1. + 2e-9];
+        let axis = Axis::from_values(raw, Tolerance::default());
+
+        assert_eq!(axis.values, [0., 2e-12, 1., 1. + 2e-9]);
+        assert_eq!(axis.merged, 0);
+    }
+
+    #[test]
+    fn index_of_merged_and_nearby_values() {
+        let raw = vec![0., 1., 1. + 5e-10, 2.];
+        let axis = Axis::from_values(raw, Tolerance::default());
+
+        assert_eq!(axis.index_of(1.), Some(1));
+        assert_eq!(axis.index_of(1. + 5e-10), Some(1));
+        // not part of the scan, but within tolerance of a grid line
+        assert_eq!(axis.index_of(2. - 1e-9), Some(2));
+        assert_eq!(axis.index_of(-1e-13), Some(0));
+
+        assert_eq!(axis.index_of(0.5), None);
+        assert_eq!(axis.index_of(3.), None);
+    }
+
+    #[test]
+    fn ordering_of_rows() {
+        let lengths = [2, 3, 1];
+        let mut detector = OrderingDetector::new(lengths[0], lengths[1], lengths[2]);
+
+        // x varies fastest
+        let mut row = 0;
+        for j in 0..lengths[1] {
+            for i in 0..lengths[0] {
+                detector.observe(row, [i, j, 0]);
+                row += 1;
+            }
+        }
+
+        assert_eq!(detector.describe(), "y slowest, x fastest");
+
+        let mut detector = OrderingDetector::new(2, 2, 1);
+        for (row, index) in [[0, 0, 0], [1, 1, 0], [0, 1, 0], [1, 0, 0]]
+            .into_iter()
+            .enumerate()
+        {
+            detector.observe(row, index);
+        }
+
+        assert_eq!(
+            detector.describe(),
+            "no consistent loop order, rows placed by coordinate"
+        );
+    }
+
+    #[test]
+    fn collector_matches_two_pass_axes() {
+        // enough rows for the collector to compact several times, with jittered coordinates
+        let (nx, ny, nz) = (40, 30, 20);
+        let mut raw = [Vec::new(), Vec::new(), Vec::new()];
+        let mut collectors = [
+            AxisCollector::new(),
+            AxisCollector::new(),
+            AxisCollector::new(),
+        ];
+
+        for i in 0..nx {
+            for j in 0..ny {
+                for k in 0..nz {
+                    // within both the absolute and the relative tolerance of the grid line
+                    let step = ((i + j + k) % 3) as f64;
+                    let jitter = |value: f64| value * (1. + step * 2e-10) + step * 4e-13;
+                    let point = [
+                        jitter(i as f64 * 0.1),
+                        jitter(j as f64),
+                        jitter(k as f64 * 10.),
+                    ];
+
+                    for ((raw, collector), value) in raw.iter_mut().zip(&mut collectors).zip(point)
+                    {
+                        raw.push(value);
+                        collector.push(value);
+                    }
+                }
+            }
+        }
+
+        for ((raw, collector), length) in raw.into_iter().zip(collectors).zip([nx, ny, nz]) {
+            let two_pass = Axis::from_values(raw, Tolerance::default());
+            let single_pass = collector.finish(Tolerance::default());
+
+            assert_eq!(single_pass.len(), length);
+            assert_eq!(single_pass.values, two_pass.values);
+            assert_eq!(single_pass.merged, two_pass.merged);
+            assert_eq!(single_pass.merged, 2 * length);
+        }
+    }
+
+    #[test]
+    fn uniform_spacing_within_tolerance() {
+        let tolerance = Tolerance::default();
+
+        // round-off in the coordinates is accepted
+        let axis = Axis::from_values(vec![0., 0.1, 0.2 + 1e-8, 0.3], tolerance);
+        let spacing = axis.uniform_spacing(1e-6).unwrap();
+        assert!((spacing - 0.1).abs() < 1e-15);
+
+        // a stretched axis is not
+        let axis = Axis::from_values(vec![0., 0.1, 0.25, 0.3], tolerance);
+        assert_eq!(axis.uniform_spacing(1e-6), None);
+
+        // nor is a deviation just above the allowed fraction of the spacing
+        let axis = Axis::from_values(vec![0., 1., 2. + 2e-6, 3.], tolerance);
+        assert_eq!(axis.uniform_spacing(1e-6), None);
+
+        let axis = Axis::from_values(vec![4.], tolerance);
+        assert_eq!(axis.uniform_spacing(1e-6), Some(1.));
+    }
+}
@@ -0,0 +1,207 @@
+//! input file formats and the opening of plain and compressed input files
+
+use anyhow::{bail, Context, Result};
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+use crate::cli;
+
+/// compression of an input file, recognised from its first bytes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Compression {
+    Plain,
+    Gzip,
+    Zstd,
+    Xz,
+}
+
+impl Compression {
+    const MAGIC: [(Compression, &'static [u8]); 3] = [
+        (Compression::Gzip, &[0x1f, 0x8b]),
+        (Compression::Zstd, &[0x28, 0xb5, 0x2f, 0xfd]),
+        (Compression::Xz, &[0xfd, b'7', b'z', b'X', b'Z', 0x00]),
+    ];
+
+    fn detect(header: &[u8]) -> Self {
+        Self::MAGIC
+            .iter()
+            .find(|(_, magic)| header.starts_with(magic))
+            .map_or(Compression::Plain, |(compression, _)| *compression)
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Compression::Plain => "uncompressed",
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+            Compression::Xz => "xz",
+        }
+    }
+
+    /// the compression implied by the extension of `path`, e.g. `.csv.gz`
+    fn from_extension(path: &Path) -> Self {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        match extension.as_str() {
+            "gz" | "gzip" => Compression::Gzip,
+            "zst" | "zstd" => Compression::Zstd,
+            "xz" => Compression::Xz,
+            _ => Compression::Plain,
+        }
+    }
+}
+
+/// the file format of an input file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Format<'a> {
+    /// a (possibly compressed) CSV file
+    Csv,
+    /// a NumPy archive of coordinate and field arrays
+    Npz,
+    /// a fortran unformatted sequential file with this layout file
+    Fortran(&'a Path),
+    /// a (possibly compressed) Tecplot ASCII file
+    Tecplot,
+    /// a MATLAB level 5 file of coordinate and field matrices
+    Mat,
+}
+
+impl<'a> Format<'a> {
+    /// the format of `path`, from the command line or its extension
+    pub(crate) fn of(path: &Path, args: &'a cli::Args) -> Result<Self> {
+        if let Some(layout) = &args.fortran_layout {
+            return Ok(Format::Fortran(layout));
+        }
+
+        // the format of compressed files is given by the extension before the compression, e.g.
+        // `mode.dat.gz`
+        let uncompressed = match Compression::from_extension(path) {
+            Compression::Plain => path.to_path_buf(),
+            _ => path.with_extension(""),
+        };
+
+        let extension = uncompressed
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        match extension.as_str() {
+            "npz" => Ok(Format::Npz),
+            "dat" | "tec" => Ok(Format::Tecplot),
+            "mat" => Ok(Format::Mat),
+            "plt" | "szplt" => bail!(
+                "{} is a binary Tecplot file, export it from Tecplot as an ASCII .dat file instead",
+                path.display()
+            ),
+            "npy" => bail!(
+                "{} is a single NumPy array without coordinates, save the coordinates and fields together with numpy.savez instead",
+                path.display()
+            ),
+            _ => Ok(Format::Csv),
+        }
+    }
+}
+
+/// open an input file, decompressing gzip, zstd and xz files on the fly as they are read
+pub(crate) fn open(path: &Path) -> Result<Box<dyn Read>> {
+    let file = std::fs::File::open(path)
+        .with_context(|| format!("failed to open input file at {}", path.display()))?;
+
+    decompress(BufReader::new(file), path)
+}
+
+/// decompress `reader` according to its first bytes, checking them against the extension of
+/// `path`
+fn decompress<R: BufRead + 'static>(mut reader: R, path: &Path) -> Result<Box<dyn Read>> {
+    let header = reader
+        .fill_buf()
+        .with_context(|| format!("failed to read from {}", path.display()))?;
+
+    let compression = Compression::detect(header);
+    let expected = Compression::from_extension(path);
+
+    if expected != Compression::Plain && expected != compression {
+        bail!(
+            "{} has the extension of a {} compressed file, but its contents are {}",
+            path.display(),
+            expected.name(),
+            compression.name()
+        );
+    }
+
+    let reader: Box<dyn Read> = match compression {
+        Compression::Plain => Box::new(reader),
+        Compression::Gzip => Box::new(flate2::bufread::MultiGzDecoder::new(reader)),
+        Compression::Zstd => Box::new(
+            zstd::Decoder::with_buffer(reader)
+                .with_context(|| format!("failed to start decompressing {}", path.display()))?,
+        ),
+        Compression::Xz => Box::new(xz2::bufread::XzDecoder::new_multi_decoder(reader)),
+    };
+
+    Ok(reader)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+
+    #[test]
+    fn detect_magic_bytes() {
+        assert_eq!(
+            Compression::detect(&[0x1f, 0x8b, 0x08, 0x00]),
+            Compression::Gzip
+        );
+        assert_eq!(
+            Compression::detect(&[0x28, 0xb5, 0x2f, 0xfd, 0x04]),
+            Compression::Zstd
+        );
+        assert_eq!(
+            Compression::detect(b"\xfd7zXZ\x00\x00\x04"),
+            Compression::Xz
+        );
+        assert_eq!(Compression::detect(b"x,y,z,u\n"), Compression::Plain);
+        // too short to be a zstd frame
+        assert_eq!(Compression::detect(&[0x28, 0xb5]), Compression::Plain);
+        assert_eq!(Compression::detect(&[]), Compression::Plain);
+    }
+
+    #[test]
+    fn decompress_gzip() {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"x,y,z,u\n").unwrap();
+        let bytes = encoder.finish().unwrap();
+
+        let mut text = String::new();
+        decompress(Cursor::new(bytes), Path::new("mode.csv.gz"))
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+
+        assert_eq!(text, "x,y,z,u\n");
+    }
+
+    #[test]
+    fn extension_does_not_match_contents() {
+        let error = decompress(Cursor::new(b"x,y,z,u\n".to_vec()), Path::new("mode.csv.gz"))
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "mode.csv.gz has the extension of a gzip compressed file, but its contents are uncompressed"
+        );
+
+        let zstd = vec![0x28, 0xb5, 0x2f, 0xfd, 0x04, 0x00];
+        let error = decompress(Cursor::new(zstd), Path::new("mode.csv.xz"))
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "mode.csv.xz has the extension of a xz compressed file, but its contents are zstd"
+        );
+    }
+}
@@ -0,0 +1,98 @@
+//! volume integrated diagnostics of a complex mode
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::path::Path;
+
+use crate::cli::{self, Rule};
+use crate::fields::{self, Field};
+use crate::grid;
+use crate::quadrature::Weights;
+use crate::vorticity;
+
+/// integrals of the mode over the grid
+#[derive(Serialize, Debug)]
+pub(crate) struct Summary {
+    /// input file the mode was read from
+    pub(crate) file: String,
+    pub(crate) time: Option<f64>,
+    pub(crate) rule: &'static str,
+    pub(crate) volume: f64,
+    /// ∫ ½|u|² dV
+    pub(crate) kinetic_energy: f64,
+    /// ∫ ½|w|² dV
+    pub(crate) enstrophy: f64,
+    /// ∫ Re(u·w*) dV
+    pub(crate) helicity: f64,
+    /// ∫ ½|u_k|² dV of each velocity component
+    pub(crate) component_energy: [f64; 3],
+}
+
+/// integrate the energy, enstrophy and helicity of the velocity and vorticity fields over the
+/// grid, and print the results
+pub(crate) fn integrate(
+    fields: &[Field],
+    axes: &grid::Axes,
+    args: &cli::Args,
+    rule: Rule,
+    file: &Path,
+) -> Result<Summary> {
+    let velocity = fields::find_vector(fields, &args.velocity_field)?;
+    let vorticity = vorticity::supplied_or_computed(fields, axes, args)?;
+    let weights = Weights::new(axes, rule);
+
+    // |f_v|² and Re(f_v g_v*) at a point, a missing imaginary part is zero
+    let squared = |field: &Field, v: usize, [i, j, k]: [usize; 3]| {
+        let re = field.real[[v, i, j, k]];
+        let im = field.imaginary.as_ref().map_or(0., |im| im[[v, i, j, k]]);
+        re * re + im * im
+    };
+    let dot = |v: usize, [i, j, k]: [usize; 3]| {
+        let ur = velocity.real[[v, i, j, k]];
+        let ui = velocity.imaginary.as_ref().map_or(0., |im| im[[v, i, j, k]]);
+        let wr = vorticity.real[[v, i, j, k]];
+        let wi = vorticity.imaginary.as_ref().map_or(0., |im| im[[v, i, j, k]]);
+        ur * wr + ui * wi
+    };
+
+    let component_energy =
+        [0, 1, 2].map(|v| weights.integrate(|i, j, k| 0.5 * squared(velocity, v, [i, j, k])));
+
+    let summary = Summary {
+        file: file.display().to_string(),
+        time: None,
+        rule: match rule {
+            Rule::Trapezoid => "trapezoid",
+            Rule::Simpson => "simpson",
+        },
+        volume: weights.integrate(|_, _, _| 1.),
+        kinetic_energy: component_energy.iter().sum(),
+        enstrophy: weights.integrate(|i, j, k| {
+            (0..3).map(|v| 0.5 * squared(&vorticity, v, [i, j, k])).sum()
+        }),
+        helicity: weights.integrate(|i, j, k| (0..3).map(|v| dot(v, [i, j, k])).sum()),
+        component_energy,
+    };
+
+    println!("volume integrals ({} rule):", summary.rule);
+    println!("    volume          {:.6e}", summary.volume);
+    println!("    kinetic energy  {:.6e}", summary.kinetic_energy);
+    println!("    enstrophy       {:.6e}", summary.enstrophy);
+    println!("    helicity        {:.6e}", summary.helicity);
+    for (v, energy) in summary.component_energy.iter().enumerate() {
+        println!("    energy of {}{}     {energy:.6e}", args.velocity_field, v + 1);
+    }
+
+    Ok(summary)
+}
+
+/// write `value` to `path` as pretty printed JSON
+pub(crate) fn write_json<T: Serialize + ?Sized>(path: &Path, value: &T) -> Result<()> {
+    let file = std::fs::File::create(path)
+        .with_context(|| format!("failed to create JSON file at {}", path.display()))?;
+
+    serde_json::to_writer_pretty(std::io::BufWriter::new(file), value)
+        .with_context(|| format!("failed to write JSON to {}", path.display()))?;
+
+    Ok(())
+}
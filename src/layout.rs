@@ -0,0 +1,338 @@
+//! choice of the VTK dataset that a grid is written as
+
+use anyhow::{bail, Result};
+use ndarray::Array4;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::cli::{self, CoordinateSystem, ImageData};
+use crate::grid;
+use crate::writer::{self, PointArray};
+
+const AXIS_NAMES: [&str; 3] = ["x", "y", "z"];
+
+/// how a grid is written
+#[derive(Clone, Debug)]
+pub(crate) enum Layout {
+    /// a rectilinear grid, which is a plane if one direction has a single point. `image` is the
+    /// spacing along each direction if it is written as image data
+    Volume { image: Option<[f64; 3]> },
+    /// a 3D structured grid with the cartesian positions of points given in another coordinate
+    /// system, and vectors rotated into cartesian components
+    Structured { system: CoordinateSystem },
+    /// a 3D structured grid with the interleaved `(x, y, z)` position of every point given
+    /// explicitly, with the first direction varying fastest
+    Curvilinear { points: Vec<f64> },
+    /// unconnected points with the interleaved coordinates `points` in `system`, in the order of
+    /// the first direction of the point data. Vectors are rotated into cartesian components as for
+    /// a structured grid
+    PointCloud {
+        points: Vec<f64>,
+        system: CoordinateSystem,
+    },
+    /// a polyline along the only direction of the input with more than one point
+    Line { axis: usize },
+}
+
+impl Layout {
+    /// pick the layout of a grid from the number of points along each direction and the spacing
+    /// of the axes
+    pub(crate) fn of(axes: &grid::Axes, args: &cli::Args) -> Result<Self> {
+        let lengths = [axes.x.len(), axes.y.len(), axes.z.len()];
+        let varying: Vec<usize> = (0..3).filter(|axis| lengths[*axis] > 1).collect();
+
+        if args.coordinate_system != CoordinateSystem::Cartesian {
+            return Ok(Layout::Structured {
+                system: args.coordinate_system,
+            });
+        }
+
+        // line data is always written as a polyline
+        let image = match varying.len() {
+            1 if !args.always_3d => None,
+            _ => image_spacing(axes, args)?,
+        };
+
+        if args.always_3d {
+            return Ok(Layout::Volume { image });
+        }
+
+        let layout = match varying.as_slice() {
+            [first, second] => {
+                println!(
+                    "planar data in the {}-{} plane, writing a 2D grid one point thick along {}",
+                    AXIS_NAMES[*first],
+                    AXIS_NAMES[*second],
+                    AXIS_NAMES[3 - first - second]
+                );
+
+                Layout::Volume { image }
+            }
+            [axis] => {
+                println!("line data along {}, writing a polyline", AXIS_NAMES[*axis]);
+
+                Layout::Line { axis: *axis }
+            }
+            _ => Layout::Volume { image },
+        };
+
+        Ok(layout)
+    }
+
+    /// file extension of the VTK files written with this layout
+    pub(crate) fn extension(&self) -> &'static str {
+        match self {
+            Layout::Volume { image: None } => "vtr",
+            Layout::Volume { image: Some(_) } => "vti",
+            Layout::Structured { .. } | Layout::Curvilinear { .. } => "vts",
+            Layout::Line { .. } | Layout::PointCloud { .. } => "vtp",
+        }
+    }
+
+    /// `output` with its extension replaced if the file type does not match this layout
+    pub(crate) fn output_path(&self, output: &Path) -> PathBuf {
+        match self {
+            Layout::Volume { image: None } => output.to_path_buf(),
+            _ if output.extension() != Some(self.extension().as_ref()) => {
+                let path = output.with_extension(self.extension());
+                println!("writing {}", path.display());
+                path
+            }
+            _ => output.to_path_buf(),
+        }
+    }
+
+    /// write the point data `arrays` on the grid with this layout
+    pub(crate) fn write<W: Write>(
+        &self,
+        writer: W,
+        axes: &grid::Axes,
+        arrays: &[PointArray],
+    ) -> Result<()> {
+        let values = [&axes.x.values, &axes.y.values, &axes.z.values];
+
+        match self {
+            Layout::Volume { image } => grid(writer, values, *image, arrays),
+            Layout::Structured { system } => {
+                let lengths = values.map(|axis| axis.len());
+
+                let mut points = Vec::with_capacity(3 * lengths.iter().product::<usize>());
+                for k in 0..lengths[2] {
+                    for j in 0..lengths[1] {
+                        for i in 0..lengths[0] {
+                            points.extend(system.position([
+                                values[0][i],
+                                values[1][j],
+                                values[2][k],
+                            ]));
+                        }
+                    }
+                }
+
+                let arrays = rotate_vectors(arrays, *system, |i, j, k| {
+                    [values[0][i], values[1][j], values[2][k]]
+                });
+
+                writer::write_structured(writer, lengths, &points, &arrays)
+            }
+            Layout::Curvilinear { points } => {
+                writer::write_structured(writer, values.map(|axis| axis.len()), points, arrays)
+            }
+            Layout::PointCloud { points, system } => {
+                let (positions, arrays) = point_cloud(points, *system, arrays);
+                writer::write_point_cloud(writer, &positions, &arrays)
+            }
+            Layout::Line { axis } => {
+                let points: Vec<f64> = (0..values[*axis].len())
+                    .flat_map(|index| {
+                        let mut point = [values[0][0], values[1][0], values[2][0]];
+                        point[*axis] = values[*axis][index];
+                        point
+                    })
+                    .collect();
+
+                writer::write_polyline(writer, &points, arrays)
+            }
+        }
+    }
+}
+
+/// write a rectilinear grid, or image data if `image` holds the spacing of the axes
+fn grid<W: Write>(
+    writer: W,
+    values: [&Vec<f64>; 3],
+    image: Option<[f64; 3]>,
+    arrays: &[PointArray],
+) -> Result<()> {
+    let [x, y, z] = values;
+
+    let Some(spacing) = image else {
+        return writer::write_rectilinear(writer, x, y, z, arrays);
+    };
+
+    writer::write_image(
+        writer,
+        [x[0], y[0], z[0]],
+        spacing,
+        [x.len(), y.len(), z.len()],
+        arrays,
+    )
+}
+
+/// the spacing of every axis if the grid should be written as image data
+fn image_spacing(axes: &grid::Axes, args: &cli::Args) -> Result<Option<[f64; 3]>> {
+    if args.image_data == ImageData::Never {
+        return Ok(None);
+    }
+
+    let spacing = [&axes.x, &axes.y, &axes.z].map(|axis| axis.uniform_spacing(args.uniform_tol));
+
+    match spacing {
+        [Some(x), Some(y), Some(z)] => {
+            println!("uniform grid with spacing ({x}, {y}, {z}), writing image data");
+            Ok(Some([x, y, z]))
+        }
+        _ if args.image_data == ImageData::Always => {
+            let uneven: Vec<&str> = (0..3)
+                .filter(|axis| spacing[*axis].is_none())
+                .map(|axis| AXIS_NAMES[axis])
+                .collect();
+
+            bail!(
+                "--image-data always needs a uniform grid, but the spacing along {} is not uniform within --uniform-tol {}",
+                uneven.join(", "),
+                args.uniform_tol
+            )
+        }
+        _ => Ok(None),
+    }
+}
+
+/// the cartesian positions of the points of a point cloud with coordinates `points` in `system`,
+/// and `arrays` with their vectors rotated into cartesian components
+fn point_cloud<'a>(
+    points: &[f64],
+    system: CoordinateSystem,
+    arrays: &'a [PointArray],
+) -> (Vec<f64>, Vec<PointArray<'a>>) {
+    let positions = points
+        .chunks_exact(3)
+        .flat_map(|q| system.position([q[0], q[1], q[2]]))
+        .collect();
+
+    let arrays = rotate_vectors(arrays, system, |i, _, _| {
+        [points[3 * i], points[3 * i + 1], points[3 * i + 2]]
+    });
+
+    (positions, arrays)
+}
+
+/// `arrays` with every 3-component array rotated from the local basis of `system` into cartesian
+/// components, where `coordinates(i, j, k)` are the coordinates of point `(i, j, k)`
+fn rotate_vectors<'a>(
+    arrays: &'a [PointArray],
+    system: CoordinateSystem,
+    coordinates: impl Fn(usize, usize, usize) -> [f64; 3],
+) -> Vec<PointArray<'a>> {
+    if system == CoordinateSystem::Cartesian {
+        return arrays.iter().map(PointArray::reborrow).collect();
+    }
+
+    arrays
+        .iter()
+        .map(|array| match array.components() {
+            3 => PointArray::owned(
+                array.name.clone(),
+                rotate(&array.values, system, &coordinates),
+            ),
+            _ => array.reborrow(),
+        })
+        .collect()
+}
+
+/// rotate the components of a vector array from the local basis of `system` into cartesian
+/// components, where `coordinates(i, j, k)` are the coordinates of point `(i, j, k)`
+fn rotate(
+    vector: &Array4<f64>,
+    system: CoordinateSystem,
+    coordinates: impl Fn(usize, usize, usize) -> [f64; 3],
+) -> Array4<f64> {
+    let (_, nx, ny, nz) = vector.dim();
+    let mut rotated = Array4::zeros((3, nx, ny, nz));
+
+    for i in 0..nx {
+        for j in 0..ny {
+            for k in 0..nz {
+                let cartesian = system.rotate(
+                    coordinates(i, j, k),
+                    [
+                        vector[[0, i, j, k]],
+                        vector[[1, i, j, k]],
+                        vector[[2, i, j, k]],
+                    ],
+                );
+
+                for (v, component) in cartesian.into_iter().enumerate() {
+                    rotated[[v, i, j, k]] = component;
+                }
+            }
+        }
+    }
+
+    rotated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::FRAC_PI_2;
+
+    #[test]
+    fn structured_vectors_are_rotated_at_their_grid_point() {
+        // e_θ at (r, θ, z) = (1, 0, 0) and (1, π/2, 0)
+        let values = [vec![1.], vec![0., FRAC_PI_2], vec![0.]];
+        let e_theta = Array4::from_shape_vec((3, 1, 2, 1), vec![0., 0., 1., 1., 0., 0.]).unwrap();
+
+        let rotated = rotate(&e_theta, CoordinateSystem::Cylindrical, |i, j, k| {
+            [values[0][i], values[1][j], values[2][k]]
+        });
+
+        let expected = [[0., 1., 0.], [-1., 0., 0.]];
+        for (j, expected) in expected.iter().enumerate() {
+            for (v, expected) in expected.iter().enumerate() {
+                assert!((rotated[[v, 0, j, 0]] - expected).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn cylindrical_point_clouds_are_written_in_cartesian_coordinates() {
+        let points = [1., 0., 0., 1., FRAC_PI_2, 0., 2., 0., 0.];
+        // a radial vector of unit length and a scalar at each point
+        let radial =
+            Array4::from_shape_vec((3, 3, 1, 1), vec![1., 1., 1., 0., 0., 0., 0., 0., 0.]).unwrap();
+        let scalar = Array4::from_shape_vec((1, 3, 1, 1), vec![1., 2., 3.]).unwrap();
+        let arrays = [
+            PointArray::borrowed("u", &radial),
+            PointArray::borrowed("p", &scalar),
+        ];
+
+        let (positions, arrays) = point_cloud(&points, CoordinateSystem::Cylindrical, &arrays);
+
+        let expected = [1., 0., 0., 0., 1., 0., 2., 0., 0.];
+        for (position, expected) in positions.iter().zip(expected) {
+            assert!((position - expected).abs() < 1e-12, "{positions:?}");
+        }
+
+        let u = &arrays[0].values;
+        for (point, expected) in [[1., 0., 0.], [0., 1., 0.], [1., 0., 0.]]
+            .iter()
+            .enumerate()
+        {
+            for (v, expected) in expected.iter().enumerate() {
+                assert!((u[[v, point, 0, 0]] - expected).abs() < 1e-12);
+            }
+        }
+        assert_eq!(arrays[1].values[[0, 2, 0, 0]], 3.);
+    }
+}
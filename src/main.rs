@@ -1,229 +1,263 @@
+mod animation;
+mod arrays;
+mod batch;
 mod cli;
+mod coordinates;
+mod derived;
+mod derivatives;
+mod fields;
+mod fortran;
+mod grid;
+mod input;
+mod integrals;
+mod layout;
+mod mat;
+mod normalize;
+mod npy;
+mod npz;
+mod points;
+mod quadrature;
+mod schema;
+mod spanwise;
+mod tecplot;
+mod validate;
+mod vortex;
+mod vorticity;
+mod writer;
 
 use anyhow::{bail, Context, Result};
 use clap::Parser;
-use serde::Deserialize;
-
-use ndarray::{Array3, Array4};
-
-#[derive(Deserialize)]
-/// column headers of the CSV file
-struct CsvData {
-    x: f64,
-    y: f64,
-    z: f64,
-    u1r: f64,
-    u2r: f64,
-    u3r: f64,
-    u1i: f64,
-    u2i: f64,
-    u3i: f64,
-    w1r: f64,
-    w2r: f64,
-    w3r: f64,
-    w1i: f64,
-    w2i: f64,
-    w3i: f64,
+use cli::PointCloud;
+use std::path::Path;
+
+/// the grid and fields read from one input file
+struct Dataset {
+    /// the grid lines, or the point indices of a curvilinear grid
+    axes: grid::Axes,
+    /// how the grid is written
+    layout: layout::Layout,
+    fields: Vec<fields::Field>,
+    /// time of the snapshot, if the schema has a time column
+    time: Option<f64>,
+    /// additional arrays computed from the fields
+    derived: Vec<writer::PointArray<'static>>,
+    /// volume integrals of the mode, if they were requested
+    integrals: Option<integrals::Summary>,
 }
 
-#[derive(vtk::DataArray)]
-/// VTK/VTR output data for paraview
-struct VtkData {
-    real_velocity: vtk::Vector3D<f64>,
-    imaginary_velocity: vtk::Vector3D<f64>,
-    total_velocity_magnitude: vtk::Scalar3D<f64>,
-    real_w: vtk::Vector3D<f64>,
-    imaginary_w: vtk::Vector3D<f64>,
-    total_w_magnitude: vtk::Scalar3D<f64>,
+/// the grid and fields of an input file, before any processing
+struct RawDataset {
+    axes: grid::Axes,
+    fields: Vec<fields::Field>,
+    time: Option<f64>,
+    /// the layout of grids that are not rectilinear, otherwise it is chosen from the axes
+    layout: Option<layout::Layout>,
 }
 
-impl VtkData {
-    fn new(
-        real_velocity: ndarray::Array4<f64>,
-        imaginary_velocity: ndarray::Array4<f64>,
-        total_velocity_magnitude: ndarray::Array3<f64>,
-        real_w: ndarray::Array4<f64>,
-        imaginary_w: ndarray::Array4<f64>,
-        total_w_magnitude: ndarray::Array3<f64>,
-    ) -> Self {
-        Self {
-            real_velocity: vtk::Vector3D::new(real_velocity),
-            imaginary_velocity: vtk::Vector3D::new(imaginary_velocity),
-            total_velocity_magnitude: vtk::Scalar3D::new(total_velocity_magnitude),
-            real_w: vtk::Vector3D::new(real_w),
-            imaginary_w: vtk::Vector3D::new(imaginary_w),
-            total_w_magnitude: vtk::Scalar3D::new(total_w_magnitude),
-        }
+fn read_dataset(path: &Path, schema: &schema::Schema, args: &cli::Args) -> Result<Dataset> {
+    let tolerance = grid::Tolerance {
+        absolute: args.abs_tol,
+        relative: args.rel_tol,
+    };
+
+    let format = input::Format::of(path, args)?;
+
+    // the other formats give their own coordinates and dimensions
+    if format != input::Format::Csv && (args.dimensions.is_some() || args.point_cloud.is_some()) {
+        bail!("--dimensions and --point-cloud only apply to CSV input");
     }
-}
 
-fn magnitude_complex(
-    nx: usize,
-    ny: usize,
-    nz: usize,
-    real: &Array4<f64>,
-    im: &Array4<f64>,
-    out: &mut Array3<f64>,
-) {
-    for i in 0..nx {
-        for j in 0..ny {
-            for k in 0..nz {
-                let mut magnitude_squared = 0.;
-
-                // the magnitude of a vector of complex numbers is the sum of the squares of all
-                // components
-                for v in 0..3 {
-                    magnitude_squared += real[[v, i, j, k]].powi(2);
-                    magnitude_squared += im[[v, i, j, k]].powi(2);
-                }
+    let raw = match format {
+        input::Format::Csv => read_csv(path, schema, args, tolerance)?,
+        input::Format::Npz => npz::read(path, schema.clone(), tolerance)?,
+        input::Format::Fortran(layout) => fortran::read(path, layout, schema.clone(), tolerance)?,
+        input::Format::Tecplot => tecplot::read(path, schema.clone(), args, tolerance)?,
+        input::Format::Mat => mat::read(path, schema.clone(), tolerance)?,
+    };
 
-                out[[i, j, k]] = magnitude_squared.sqrt();
-            }
+    let RawDataset {
+        mut axes,
+        mut fields,
+        time,
+        layout,
+    } = raw;
+
+    spanwise::expand(&mut axes, &mut fields, args, tolerance)?;
+
+    normalize::normalize(&mut fields, &axes, args)?;
+
+    let mut derived = Vec::new();
+
+    if args.amplitude_phase {
+        if fields.iter().all(|field| field.imaginary.is_none()) {
+            println!("warning: no complex fields found, no amplitude or phase will be written");
         }
+        derived.extend(derived::amplitude_phase(&fields, args.unwrap_phase));
     }
-}
 
-fn determine_spans(file: std::fs::File) -> Result<(vtk::Spans3D, vtk::Mesh3D<f64, vtk::Binary>)> {
-    let reader = std::io::BufReader::new(file);
-    let mut reader = csv::ReaderBuilder::new()
-        .has_headers(true)
-        .from_reader(reader);
+    if args.validate {
+        derived.extend(validate::validate(&fields, &axes, args)?);
+    }
 
-    let mut x = Vec::new();
-    let mut y = Vec::new();
-    let mut z = Vec::new();
+    if args.check_vorticity {
+        derived.extend(vorticity::check_vorticity(&fields, &axes, args)?);
+    }
 
-    for (idx, row) in reader.deserialize().enumerate() {
-        let row: CsvData =
-            row.with_context(|| format!("failed to serialize row {} of csv", idx + 2))?;
+    if args.energy_densities {
+        let velocity = fields::find_vector(&fields, &args.velocity_field)?;
+        let vorticity = vorticity::supplied_or_computed(&fields, &axes, args)?;
+        derived.extend(derived::energy_densities(velocity, &vorticity));
+    }
 
-        if !x.contains(&row.x) {
-            x.push(row.x)
-        }
-        if !y.contains(&row.y) {
-            y.push(row.y)
-        }
-        if !z.contains(&row.z) {
-            z.push(row.z)
-        }
+    if args.vortex_criteria {
+        derived.extend(vortex::vortex_criteria(&fields, &axes, args)?);
     }
 
-    let spans = vtk::Spans3D::new(x.len(), y.len(), z.len());
-    let mesh = vtk::Mesh3D::<f64, vtk::Binary>::new(x, y, z);
-    Ok((spans, mesh))
+    let integrals = match args.integrals {
+        Some(rule) => {
+            let mut summary = integrals::integrate(&fields, &axes, args, rule, path)?;
+            summary.time = time;
+            Some(summary)
+        }
+        None => None,
+    };
+
+    let layout = match layout {
+        Some(layout) => layout,
+        None => layout::Layout::of(&axes, args)?,
+    };
+
+    Ok(Dataset {
+        axes,
+        layout,
+        fields,
+        time,
+        derived,
+        integrals,
+    })
 }
 
-fn main() -> Result<()> {
-    let args = cli::Args::parse();
+/// read a CSV file, placing its rows on a rectilinear or curvilinear grid or a point cloud
+fn read_csv(
+    path: &Path,
+    schema: &schema::Schema,
+    args: &cli::Args,
+    tolerance: grid::Tolerance,
+) -> Result<RawDataset> {
+    let reader = input::open(path)?;
+
+    let (axes, samples) = points::determine_spans(reader, schema.clone(), tolerance)
+        .with_context(|| format!("failed to read span and mesh information from CSV {}", path.display()))?;
+
+    let time = samples.time;
+
+    let (axes, fields, layout) = match &args.dimensions {
+        Some(dimensions) => {
+            let lengths = points::parse_dimensions(dimensions)?;
+            println!(
+                "curvilinear mesh size is ({},{},{})",
+                lengths[0], lengths[1], lengths[2]
+            );
+
+            let (points, fields) = samples.into_curvilinear(lengths)?;
+            let layout = layout::Layout::Curvilinear { points };
+            (grid::Axes::indices(lengths, tolerance), fields, Some(layout))
+        }
+        None if args.point_cloud == Some(PointCloud::Always) => point_cloud(samples, args, tolerance),
+        None => {
+            let nx = axes.x.len();
+            let ny = axes.y.len();
+            let nz = axes.z.len();
+
+            println!("mesh size is ({nx},{ny},{nz})");
+            axes.report_merges();
+
+            match samples.place(&axes) {
+                Ok(placement) => {
+                    let fields = samples.into_placed(&axes, &placement);
+                    (axes, fields, None)
+                }
+                Err(error) if args.point_cloud == Some(PointCloud::Fallback) => {
+                    println!("warning: {error:#}");
+                    println!("writing the rows as a point cloud instead");
 
-    let file = std::fs::File::open(&args.csv_path)
-        .with_context(|| format!("failed to open CSV file at {}", args.csv_path.display()))?;
+                    let cloud = point_cloud(samples, args, tolerance);
+                    coordinates::check_indexed(args, &cloud.1, "a point cloud")?;
+                    cloud
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    };
+
+    Ok(RawDataset {
+        axes,
+        fields,
+        time,
+        layout,
+    })
+}
 
-    let (spans, mesh) = determine_spans(file)
-        .with_context(|| format!("failed to read span and mesh information from CSV {} on initial pass", args.csv_path.display()))?;
+/// the rows of a CSV as unconnected points, with index axes of `(rows, 1, 1)` points
+fn point_cloud(
+    samples: points::Samples,
+    args: &cli::Args,
+    tolerance: grid::Tolerance,
+) -> (grid::Axes, Vec<fields::Field>, Option<layout::Layout>) {
+    let (points, fields) = samples.into_point_cloud();
+    let axes = grid::Axes::indices([points.len() / 3, 1, 1], tolerance);
 
-    let nx = spans.x_len();
-    let ny = spans.y_len();
-    let nz = spans.z_len();
+    println!("point cloud of {} points", points.len() / 3);
 
-    println!("mesh size is ({nx},{ny},{nz})");
+    let layout = layout::Layout::PointCloud {
+        points,
+        system: args.coordinate_system,
+    };
 
-    // now, re-open the file to refresh the reader
-    let file = std::fs::File::open(&args.csv_path)
-        .with_context(|| format!("failed to open CSV file at {}", args.csv_path.display()))?;
-    let reader = std::io::BufReader::new(file);
+    (axes, fields, Some(layout))
+}
 
+fn write_dataset(output: &Path, dataset: &Dataset) -> Result<()> {
     // open the writer
-    let writer = std::fs::File::create(&args.output)
-        .with_context(|| format!("failed to create output file at {}", args.output.display()))?;
+    let writer = std::fs::File::create(output)
+        .with_context(|| format!("failed to create output file at {}", output.display()))?;
     let writer = std::io::BufWriter::new(writer);
 
-    let mut reader = csv::ReaderBuilder::new()
-        .has_headers(true)
-        .from_reader(reader);
-
-    let mut iter = reader.deserialize().enumerate();
-
+    let arrays: Vec<_> = dataset
+        .fields
+        .iter()
+        .flat_map(fields::Field::point_arrays)
+        .chain(dataset.derived.iter().map(writer::PointArray::reborrow))
+        .collect();
 
-    let mut real_velocity: Array4<f64> = Array4::zeros((3, nx, ny, nz));
-    let mut imaginary_velocity: Array4<f64> = Array4::zeros((3, nx, ny, nz));
-    let mut total_velocity_magnitude: Array3<f64> = Array3::zeros((nx, ny, nz));
-    let mut real_w: Array4<f64> = Array4::zeros((3, nx, ny, nz));
-    let mut imaginary_w: Array4<f64> = Array4::zeros((3, nx, ny, nz));
-    let mut total_w_magnitude: Array3<f64> = Array3::zeros((nx, ny, nz));
+    dataset
+        .layout
+        .write(writer, &dataset.axes, &arrays)
+        .with_context(|| "failed to write final vtk file")?;
 
-    for i in 0..nx {
-        for j in 0..ny {
-            for k in 0..nz {
-                // read the next row in the CSV, error if the row does not exist
-                let (idx, row) = if let Some(row) = iter.next() {
-                    row
-                } else {
-                    bail!("CSV was shorter than expected, unable to find data point for ({i},{j},{k}) - the wrong value of `--n` may have been chosen");
-                };
-
-                let row: CsvData =
-                    row.with_context(|| format!("failed to serialize row {} of csv", idx + 2))?;
+    Ok(())
+}
 
+fn main() -> Result<()> {
+    let args = cli::Args::parse();
 
-                //
-                // pull velocity information into containers
-                //
+    coordinates::check_supported(&args)?;
 
-                real_velocity[[0, i, j, k]] = row.u1r;
-                real_velocity[[1, i, j, k]] = row.u2r;
-                real_velocity[[2, i, j, k]] = row.u3r;
+    let schema = schema::Schema::from_args(&args)?;
 
-                imaginary_velocity[[0, i, j, k]] = row.u1i;
-                imaginary_velocity[[1, i, j, k]] = row.u2i;
-                imaginary_velocity[[2, i, j, k]] = row.u3i;
+    let Some(csv_path) = &args.csv_path else {
+        return batch::convert_all(&args, &schema);
+    };
 
-                real_w[[0, i, j, k]] = row.w1r;
-                real_w[[1, i, j, k]] = row.w2r;
-                real_w[[2, i, j, k]] = row.w3r;
+    let dataset = read_dataset(csv_path, &schema, &args)?;
 
-                imaginary_w[[0, i, j, k]] = row.w1i;
-                imaginary_w[[1, i, j, k]] = row.w2i;
-                imaginary_w[[2, i, j, k]] = row.w3i;
-            }
-        }
+    if let (Some(path), Some(summary)) = (&args.integrals_json, &dataset.integrals) {
+        integrals::write_json(path, summary)?;
     }
 
-    if let Some(_) = iter.next() {
-        bail!("unread data in csv - this should not happen");
+    if let Some(frames) = args.phase_frames {
+        return animation::write_phase_animation(&args.output, frames, &dataset);
     }
 
-    magnitude_complex(
-        nx,
-        ny,
-        nz,
-        &real_velocity,
-        &imaginary_velocity,
-        &mut total_velocity_magnitude,
-    );
-    magnitude_complex(
-        nx,
-        ny,
-        nz,
-        &real_w,
-        &imaginary_w,
-        &mut total_w_magnitude,
-    );
-
-    let data = VtkData::new(
-        real_velocity,
-        imaginary_velocity,
-        total_velocity_magnitude,
-        real_w,
-        imaginary_w,
-        total_w_magnitude,
-    );
-
-
-    let domain = vtk::Rectilinear3D::new(mesh, spans);
-    let vtk_write = vtk::VtkData::new(domain, data);
-
-    vtk::write_vtk(writer, vtk_write).with_context(|| "failed to write final vtk file")?;
-
-    Ok(())
+    write_dataset(&dataset.layout.output_path(&args.output), &dataset)
 }
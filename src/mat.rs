@@ -0,0 +1,470 @@
+//! reader for MATLAB level 5 `.mat` files, as written by `save` with `-v6` or the default `-v7`
+//!
+//! a file is a 128 byte header followed by data elements, each with a tag giving its type and
+//! size. Every variable is a matrix element, compressed with zlib in `-v7` files, holding the
+//! array flags, dimensions, name and the real and imaginary parts of its values in column major
+//! order
+
+use anyhow::{bail, Context, Result};
+use std::io::Read;
+use std::path::Path;
+
+use crate::arrays::{self, Array, Variables};
+use crate::grid;
+use crate::npy::Dtype;
+use crate::schema::Schema;
+use crate::RawDataset;
+
+const HEADER_LENGTH: usize = 128;
+
+// data element types
+const MI_INT32: u32 = 5;
+const MI_UINT32: u32 = 6;
+const MI_MATRIX: u32 = 14;
+const MI_COMPRESSED: u32 = 15;
+
+// classes of numeric matrices, from mxDOUBLE_CLASS to mxUINT64_CLASS
+const NUMERIC_CLASSES: std::ops::RangeInclusive<u32> = 6..=15;
+const COMPLEX_FLAG: u32 = 0x800;
+
+/// the data elements of a file or of a matrix, read one at a time
+struct Elements<'a> {
+    data: &'a [u8],
+    offset: usize,
+    little_endian: bool,
+    /// whether every element is padded to a multiple of eight bytes, which holds for the
+    /// elements inside a matrix but not for compressed variables at the top level of a file
+    padded: bool,
+}
+
+impl<'a> Elements<'a> {
+    fn new(data: &'a [u8], little_endian: bool, padded: bool) -> Self {
+        Self {
+            data,
+            offset: 0,
+            little_endian,
+            padded,
+        }
+    }
+
+    fn u32(&self, bytes: &[u8]) -> u32 {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        }
+    }
+
+    /// the type and contents of the next element, or `None` at the end of the data
+    fn next(&mut self) -> Result<Option<(u32, &'a [u8])>> {
+        let data = self.data;
+        let Some(tag) = data.get(self.offset..self.offset + 8) else {
+            return Ok(None);
+        };
+
+        let first = self.u32(&tag[..4]);
+
+        // small elements of up to four bytes pack their size into the upper half of the type
+        if first >> 16 != 0 {
+            let size = (first >> 16) as usize;
+            if size > 4 {
+                bail!("malformed small data element of {size} bytes");
+            }
+
+            self.offset += 8;
+            return Ok(Some((first & 0xffff, &tag[4..4 + size])));
+        }
+
+        let size = self.u32(&tag[4..]) as usize;
+        let start = self.offset + 8;
+        let Some(contents) = data.get(start..start + size) else {
+            bail!("data element of {size} bytes runs past the end of the file, which may be truncated");
+        };
+
+        self.offset = if self.padded {
+            start + size.next_multiple_of(8)
+        } else {
+            start + size
+        };
+
+        Ok(Some((first, contents)))
+    }
+
+    /// the contents of the next element, which must be of type `expected`
+    fn expect(&mut self, expected: u32, what: &str) -> Result<&'a [u8]> {
+        match self.next()? {
+            Some((kind, contents)) if kind == expected => Ok(contents),
+            Some((kind, _)) => {
+                bail!("expected the {what} of a matrix but found a data element of type {kind}")
+            }
+            None => bail!("matrix ends before its {what}"),
+        }
+    }
+
+    /// decode the numbers of an element of any numeric type
+    fn numbers(&self, kind: u32, contents: &[u8]) -> Result<Vec<f64>> {
+        let dtype = match kind {
+            1 => "i1",
+            2 => "u1",
+            3 => "i2",
+            4 => "u2",
+            5 => "i4",
+            6 => "u4",
+            7 => "f4",
+            9 => "f8",
+            12 => "i8",
+            13 => "u8",
+            _ => bail!("data element of type {kind} does not hold numbers"),
+        };
+        let order = if self.little_endian { '<' } else { '>' };
+
+        let (values, _) = Dtype::parse(&format!("{order}{dtype}"))?.decode(contents);
+        Ok(values)
+    }
+}
+
+/// a numeric matrix, or `None` for cell arrays, structs, strings and sparse matrices
+fn matrix(contents: &[u8], little_endian: bool) -> Result<Option<(String, Array)>> {
+    let mut elements = Elements::new(contents, little_endian, true);
+
+    let flags = elements.expect(MI_UINT32, "array flags")?;
+    let flags = elements.u32(flags);
+    let class = flags & 0xff;
+
+    let dimensions = elements.expect(MI_INT32, "dimensions")?;
+    let shape: Vec<usize> = elements
+        .numbers(MI_INT32, dimensions)?
+        .into_iter()
+        .map(|length| length as usize)
+        .collect();
+
+    let name = match elements.next()? {
+        Some((_, name)) => String::from_utf8_lossy(name).into_owned(),
+        None => bail!("matrix ends before its name"),
+    };
+
+    if !NUMERIC_CLASSES.contains(&class) {
+        println!("skipping MATLAB variable `{name}`, only numeric matrices can be converted");
+        return Ok(None);
+    }
+
+    let mut part = || -> Result<Vec<f64>> {
+        let Some((kind, contents)) = elements.next()? else {
+            bail!("matrix `{name}` ends before its values");
+        };
+        let values = elements.numbers(kind, contents)?;
+
+        if values.len() != shape.iter().product::<usize>() {
+            bail!(
+                "matrix `{name}` holds {} values but has dimensions {shape:?}",
+                values.len()
+            );
+        }
+
+        Ok(values)
+    };
+
+    let real = part()?;
+    let imaginary = match flags & COMPLEX_FLAG {
+        0 => None,
+        _ => Some(part()?),
+    };
+
+    // MATLAB has no 1D arrays, so vectors are stored as 1 x n or n x 1 matrices
+    let shape = match shape[..] {
+        [rows, columns] if rows == 1 || columns == 1 => vec![rows * columns],
+        _ => shape,
+    };
+
+    Ok(Some((
+        name,
+        Array::from_column_major(shape, real, imaginary),
+    )))
+}
+
+/// read every numeric variable of a MATLAB file
+fn read_variables(data: &[u8], path: &Path) -> Result<Variables> {
+    let Some(header) = data.get(..HEADER_LENGTH) else {
+        bail!("file is too short to be a MATLAB file");
+    };
+
+    let little_endian = match &header[126..] {
+        b"IM" => true,
+        b"MI" => false,
+        _ => bail!("not a MATLAB level 5 file, only files saved with -v6 or -v7 can be read (-v4 files are not supported)"),
+    };
+
+    if header.starts_with(b"MATLAB 7.3") {
+        bail!("MATLAB -v7.3 files are HDF5 files and are not supported, save the variables with -v7 instead");
+    }
+
+    let mut variables = Variables::new(format!("the variables of {}", path.display()));
+    let mut elements = Elements::new(&data[HEADER_LENGTH..], little_endian, false);
+
+    while let Some((kind, contents)) = elements.next()? {
+        let decompressed;
+        let (kind, contents) = match kind {
+            MI_COMPRESSED => {
+                let mut buffer = Vec::new();
+                flate2::read::ZlibDecoder::new(contents)
+                    .read_to_end(&mut buffer)
+                    .with_context(|| "failed to decompress a compressed variable")?;
+                decompressed = buffer;
+
+                match Elements::new(&decompressed, little_endian, false).next()? {
+                    Some(element) => element,
+                    None => continue,
+                }
+            }
+            _ => (kind, contents),
+        };
+
+        if kind != MI_MATRIX {
+            continue;
+        }
+
+        if let Some((name, array)) = matrix(contents, little_endian)? {
+            variables.insert(name, array);
+        }
+    }
+
+    Ok(variables)
+}
+
+/// read the grid and every field of the schema from a MATLAB file
+pub(crate) fn read(path: &Path, schema: Schema, tolerance: grid::Tolerance) -> Result<RawDataset> {
+    let data = std::fs::read(path)
+        .with_context(|| format!("failed to read MATLAB file at {}", path.display()))?;
+
+    let mut variables = read_variables(&data, path)
+        .with_context(|| format!("failed to read MATLAB file {}", path.display()))?;
+
+    arrays::read(&mut variables, schema, tolerance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arrays::Arrays;
+    use std::io::Write;
+
+    const MI_INT8: u32 = 1;
+    const MI_DOUBLE: u32 = 9;
+    const MX_CELL_CLASS: u32 = 1;
+    const MX_DOUBLE_CLASS: u32 = 6;
+
+    /// the bytes of a file in one byte order
+    struct File {
+        little_endian: bool,
+    }
+
+    impl File {
+        fn u32(&self, value: u32) -> [u8; 4] {
+            if self.little_endian {
+                value.to_le_bytes()
+            } else {
+                value.to_be_bytes()
+            }
+        }
+
+        fn header(&self, text: &str) -> Vec<u8> {
+            let mut header = vec![b' '; HEADER_LENGTH];
+            header[..text.len()].copy_from_slice(text.as_bytes());
+            header[126..].copy_from_slice(if self.little_endian { b"IM" } else { b"MI" });
+            header
+        }
+
+        /// a data element, using the small element format for up to four bytes if `padded`
+        fn element(&self, out: &mut Vec<u8>, kind: u32, contents: &[u8], padded: bool) {
+            if padded && contents.len() <= 4 {
+                out.extend(self.u32((contents.len() as u32) << 16 | kind));
+                out.extend(contents);
+                out.resize(out.len() + 4 - contents.len(), 0);
+                return;
+            }
+
+            out.extend(self.u32(kind));
+            out.extend(self.u32(contents.len() as u32));
+            out.extend(contents);
+            if padded {
+                out.resize(out.len().next_multiple_of(8), 0);
+            }
+        }
+
+        /// a matrix element of `class`, with the imaginary part stored as int8 like MATLAB does
+        /// for small whole numbers
+        fn matrix(
+            &self,
+            class: u32,
+            name: &str,
+            dimensions: &[u32],
+            real: &[f64],
+            imaginary: Option<&[i8]>,
+        ) -> Vec<u8> {
+            let mut contents = Vec::new();
+
+            let flags = class | if imaginary.is_some() { COMPLEX_FLAG } else { 0 };
+            let flags = [self.u32(flags), [0; 4]].concat();
+            self.element(&mut contents, MI_UINT32, &flags, true);
+
+            let dimensions: Vec<u8> = dimensions.iter().flat_map(|d| self.u32(*d)).collect();
+            self.element(&mut contents, MI_INT32, &dimensions, true);
+            self.element(&mut contents, MI_INT8, name.as_bytes(), true);
+
+            let real: Vec<u8> = real
+                .iter()
+                .flat_map(|value| match self.little_endian {
+                    true => value.to_le_bytes(),
+                    false => value.to_be_bytes(),
+                })
+                .collect();
+            self.element(&mut contents, MI_DOUBLE, &real, true);
+
+            if let Some(imaginary) = imaginary {
+                let imaginary: Vec<u8> = imaginary.iter().map(|value| *value as u8).collect();
+                self.element(&mut contents, MI_INT8, &imaginary, true);
+            }
+
+            let mut out = Vec::new();
+            self.element(&mut out, MI_MATRIX, &contents, false);
+            out
+        }
+
+        fn compressed(&self, element: &[u8]) -> Vec<u8> {
+            let mut encoder =
+                flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(element).unwrap();
+
+            let mut out = Vec::new();
+            self.element(&mut out, MI_COMPRESSED, &encoder.finish().unwrap(), false);
+            out
+        }
+    }
+
+    fn read(data: &[u8]) -> Result<Variables> {
+        read_variables(data, Path::new("mode.mat"))
+    }
+
+    #[test]
+    fn v6_and_v7_files_in_both_byte_orders() {
+        for little_endian in [true, false] {
+            for compressed in [false, true] {
+                let file = File { little_endian };
+                let variable = |element: Vec<u8>| match compressed {
+                    true => file.compressed(&element),
+                    false => element,
+                };
+
+                let mut data = file.header("MATLAB 5.0 MAT-file");
+                data.extend(variable(file.matrix(
+                    MX_DOUBLE_CLASS,
+                    "x",
+                    &[1, 3],
+                    &[0., 1., 2.],
+                    None,
+                )));
+                data.extend(variable(file.matrix(
+                    MX_DOUBLE_CLASS,
+                    "y",
+                    &[2, 1],
+                    &[0., 5.],
+                    None,
+                )));
+                data.extend(variable(file.matrix(
+                    MX_DOUBLE_CLASS,
+                    "pressure",
+                    &[3, 2],
+                    &[0., 1., 2., 10., 11., 12.],
+                    Some(&[-1, -2, -3, -4, -5, -6]),
+                )));
+                data.extend(variable(file.matrix(
+                    MX_DOUBLE_CLASS,
+                    "Re",
+                    &[1, 1],
+                    &[1e3],
+                    None,
+                )));
+                data.extend(variable(file.matrix(
+                    MX_CELL_CLASS,
+                    "notes",
+                    &[1, 1],
+                    &[],
+                    None,
+                )));
+
+                let mut variables = read(&data).unwrap();
+                assert_eq!(variables.names(), ["x", "y", "pressure"]);
+
+                // row and column vectors are one dimensional
+                assert_eq!(variables.array("x").unwrap().shape, [3]);
+                assert_eq!(variables.array("y").unwrap().real, [0., 5.]);
+
+                // column major values in row major order
+                let pressure = variables.array("pressure").unwrap();
+                assert_eq!(pressure.shape, [3, 2]);
+                assert_eq!(pressure.real, [0., 10., 1., 11., 2., 12.]);
+                assert_eq!(pressure.imaginary.unwrap(), [-1., -4., -2., -5., -3., -6.]);
+
+                assert_eq!(variables.array("Re").unwrap().real, [1e3]);
+            }
+        }
+    }
+
+    #[test]
+    fn planar_files_with_ndgrid_coordinates() {
+        let file = File {
+            little_endian: true,
+        };
+
+        // [x, y] = ndgrid(0:2, [0 5]) and p = 10 x + y / 5, with no z
+        let mut data = file.header("MATLAB 5.0 MAT-file");
+        for (name, values) in [
+            ("x", [0., 1., 2., 0., 1., 2.]),
+            ("y", [0., 0., 0., 5., 5., 5.]),
+            ("p", [0., 10., 20., 1., 11., 21.]),
+        ] {
+            data.extend(file.matrix(MX_DOUBLE_CLASS, name, &[3, 2], &values, None));
+        }
+
+        let mut variables = read(&data).unwrap();
+        let raw = arrays::read(
+            &mut variables,
+            Schema::default(),
+            grid::Tolerance::default(),
+        )
+        .unwrap();
+
+        assert_eq!(raw.axes.x.values, [0., 1., 2.]);
+        assert_eq!(raw.axes.y.values, [0., 5.]);
+        assert_eq!(raw.axes.z.values, [0.]);
+        assert_eq!(raw.fields[0].real[[0, 2, 1, 0]], 21.);
+    }
+
+    #[test]
+    fn unsupported_files_are_rejected() {
+        let file = File {
+            little_endian: true,
+        };
+
+        let error = read(&file.header("MATLAB 7.3 MAT-file")).err().unwrap();
+        assert!(error.to_string().contains("-v7.3"), "{error}");
+
+        let error = read(&[0; HEADER_LENGTH]).err().unwrap();
+        assert!(
+            error.to_string().contains("not a MATLAB level 5 file"),
+            "{error}"
+        );
+
+        let mut data = file.header("MATLAB 5.0 MAT-file");
+        data.extend(file.matrix(MX_DOUBLE_CLASS, "x", &[2, 2], &[0., 1., 2.], None));
+        let error = read(&data).err().unwrap();
+        assert!(error.to_string().contains("holds 3 values"), "{error}");
+
+        let mut data = file.header("MATLAB 5.0 MAT-file");
+        data.extend(file.matrix(MX_DOUBLE_CLASS, "x", &[1, 3], &[0., 1., 2.], None));
+        data.truncate(data.len() - 8);
+        let error = read(&data).err().unwrap();
+        assert!(error.to_string().contains("runs past the end"), "{error}");
+    }
+}
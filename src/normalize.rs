@@ -0,0 +1,237 @@
+//! removal of the arbitrary complex phase and amplitude of eigenmodes
+
+use anyhow::{bail, Context, Result};
+
+use crate::cli::{self, PhaseReference, ScaleReference};
+use crate::fields::Field;
+use crate::grid;
+use crate::quadrature::Weights;
+
+/// rotate (and optionally rescale) every complex field by a common factor, so that the chosen
+/// reference value of the reference field is real and positive
+pub(crate) fn normalize(fields: &mut [Field], axes: &grid::Axes, args: &cli::Args) -> Result<()> {
+    if args.normalize_phase.is_none() && args.normalize_scale.is_none() {
+        return Ok(());
+    }
+
+    let reference = match &args.reference_field {
+        Some(name) => fields
+            .iter()
+            .position(|field| &field.name == name)
+            .with_context(|| format!("reference field `{name}` does not exist"))?,
+        None => fields
+            .iter()
+            .position(|field| field.imaginary.is_some())
+            .with_context(|| "no complex field to normalise against")?,
+    };
+
+    let field = &fields[reference];
+    let Some(imaginary) = &field.imaginary else {
+        bail!("reference field `{}` is not complex", field.name);
+    };
+
+    if let Some(component) = args.reference_component {
+        if component == 0 || component > field.components() {
+            bail!(
+                "reference component {component} is out of range for `{}`, which has {} components",
+                field.name,
+                field.components()
+            );
+        }
+    }
+
+    // the complex value of component `v` at a point
+    let value = |v: usize, [i, j, k]: [usize; 3]| (field.real[[v, i, j, k]], imaginary[[v, i, j, k]]);
+    let norm_squared = |point: [usize; 3]| {
+        (0..field.components())
+            .map(|v| {
+                let (re, im) = value(v, point);
+                re * re + im * im
+            })
+            .sum::<f64>()
+    };
+    // the requested component, or the one with the largest amplitude at `point`
+    let component_at = |point: [usize; 3]| match args.reference_component {
+        Some(component) => component - 1,
+        None => (0..field.components())
+            .max_by(|a, b| {
+                let (ar, ai) = value(*a, point);
+                let (br, bi) = value(*b, point);
+                ar.hypot(ai).total_cmp(&br.hypot(bi))
+            })
+            .unwrap_or_default(),
+    };
+
+    let nx = axes.x.len();
+    let ny = axes.y.len();
+    let nz = axes.z.len();
+
+    let mut max_point = [0, 0, 0];
+    let mut max_norm_squared = 0.;
+    for i in 0..nx {
+        for j in 0..ny {
+            for k in 0..nz {
+                let magnitude_squared = norm_squared([i, j, k]);
+                if magnitude_squared > max_norm_squared {
+                    max_norm_squared = magnitude_squared;
+                    max_point = [i, j, k];
+                }
+            }
+        }
+    }
+
+    if max_norm_squared == 0. {
+        bail!("reference field `{}` is zero everywhere", field.name);
+    }
+
+    let weights = Weights::trapezoid(axes);
+
+    // the rotation e^{-iθ} that makes the reference value real and positive
+    let rotation = match args.normalize_phase {
+        Some(reference_kind) => {
+            let (re, im, description) = match reference_kind {
+                PhaseReference::MaxAmplitude => {
+                    let component = component_at(max_point);
+                    let (re, im) = value(component, max_point);
+                    (re, im, format!("component {} at the point of max |{}|", component + 1, field.name))
+                }
+                PhaseReference::Probe => {
+                    let probe = args
+                        .probe
+                        .as_deref()
+                        .with_context(|| "--probe X,Y,Z is required to normalise against a probe")?;
+                    let point = nearest_point(axes, probe)?;
+                    let component = component_at(point);
+                    let (re, im) = value(component, point);
+                    (re, im, format!("component {} at the probe", component + 1))
+                }
+                PhaseReference::Integral => {
+                    let component = args.reference_component.unwrap_or(1) - 1;
+                    let re = weights.integrate(|i, j, k| value(component, [i, j, k]).0);
+                    let im = weights.integrate(|i, j, k| value(component, [i, j, k]).1);
+                    (re, im, format!("the integral of component {}", component + 1))
+                }
+            };
+
+            let magnitude = re.hypot(im);
+            if magnitude == 0. {
+                bail!("the reference value ({description} of `{}`) is zero, unable to normalise the phase", field.name);
+            }
+
+            println!(
+                "rotating complex fields by {} rad so that {description} of `{}` is real and positive",
+                -im.atan2(re),
+                field.name
+            );
+
+            (re / magnitude, -im / magnitude)
+        }
+        None => (1., 0.),
+    };
+
+    let scale = match args.normalize_scale {
+        Some(ScaleReference::MaxAmplitude) => 1. / max_norm_squared.sqrt(),
+        Some(ScaleReference::KineticEnergy) => {
+            let energy = weights.integrate(|i, j, k| 0.5 * norm_squared([i, j, k]));
+            if energy <= 0. {
+                bail!("kinetic energy of `{}` is zero, unable to normalise", field.name);
+            }
+            1. / energy.sqrt()
+        }
+        None => 1.,
+    };
+
+    if args.normalize_scale.is_some() {
+        println!("scaling complex fields by {scale}");
+    }
+
+    let factor = (scale * rotation.0, scale * rotation.1);
+
+    for field in fields.iter_mut() {
+        let Some(imaginary) = &mut field.imaginary else {
+            continue;
+        };
+
+        for (re, im) in field.real.iter_mut().zip(imaginary.iter_mut()) {
+            let (a, b) = (*re, *im);
+            *re = a * factor.0 - b * factor.1;
+            *im = a * factor.1 + b * factor.0;
+        }
+    }
+
+    Ok(())
+}
+
+/// the grid point closest to a probe location given as `x,y,z`
+fn nearest_point(axes: &grid::Axes, probe: &str) -> Result<[usize; 3]> {
+    let coordinates = probe
+        .split(',')
+        .map(|value| value.trim().parse::<f64>())
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("failed to parse probe location `{probe}`"))?;
+
+    let [x, y, z] = coordinates[..] else {
+        bail!("probe location `{probe}` should be three comma separated coordinates");
+    };
+
+    let nearest = |axis: &[f64], value: f64| {
+        (0..axis.len())
+            .min_by(|a, b| (axis[*a] - value).abs().total_cmp(&(axis[*b] - value).abs()))
+            .unwrap_or_default()
+    };
+
+    Ok([
+        nearest(&axes.x.values, x),
+        nearest(&axes.y.values, y),
+        nearest(&axes.z.values, z),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use ndarray::Array4;
+
+    #[test]
+    fn phase_is_zero_at_the_probe() {
+        let tolerance = grid::Tolerance::default();
+        let axes = grid::Axes {
+            x: grid::Axis::from_values(vec![0., 1., 2., 3.], tolerance),
+            y: grid::Axis::from_values(vec![0.], tolerance),
+            z: grid::Axis::from_values(vec![0.], tolerance),
+        };
+
+        // 2 e^{i(0.7 + x)}
+        let mut fields = vec![Field {
+            name: "p".into(),
+            real: Array4::from_shape_fn((1, 4, 1, 1), |(_, i, _, _)| 2. * (0.7 + i as f64).cos()),
+            imaginary: Some(Array4::from_shape_fn((1, 4, 1, 1), |(_, i, _, _)| {
+                2. * (0.7 + i as f64).sin()
+            })),
+        }];
+
+        let args = cli::Args::parse_from([
+            "burak-vtk",
+            "--csv-path",
+            "in.csv",
+            "--output",
+            "out.vtr",
+            "--normalize-phase",
+            "probe",
+            "--probe",
+            "2.1,0,0",
+        ]);
+
+        normalize(&mut fields, &axes, &args).unwrap();
+
+        let imaginary = fields[0].imaginary.as_ref().unwrap();
+        for i in 0..4 {
+            let (re, im) = (fields[0].real[[0, i, 0, 0]], imaginary[[0, i, 0, 0]]);
+            let phase = i as f64 - 2.;
+
+            assert!((re - 2. * phase.cos()).abs() < 1e-12, "{re}");
+            assert!((im - 2. * phase.sin()).abs() < 1e-12, "{im}");
+        }
+    }
+}
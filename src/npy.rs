@@ -0,0 +1,288 @@
+//! reader for single arrays in the NumPy `.npy` format
+
+use anyhow::{bail, Context, Result};
+use std::io::Read;
+
+use crate::arrays::Array;
+
+/// the element type of an array, written as in the `descr` of an npy header, e.g. `<f8`
+pub(crate) struct Dtype {
+    little_endian: bool,
+    kind: char,
+    size: usize,
+}
+
+impl Dtype {
+    pub(crate) fn parse(descr: &str) -> Result<Self> {
+        let mut chars = descr.chars();
+
+        let little_endian = match chars.next() {
+            Some('<' | '|' | '=') => true,
+            Some('>') => false,
+            _ => bail!("unsupported npy dtype `{descr}`"),
+        };
+        let kind = chars.next().unwrap_or_default();
+        let size = chars
+            .as_str()
+            .parse::<usize>()
+            .with_context(|| format!("unsupported npy dtype `{descr}`"))?;
+
+        match (kind, size) {
+            ('f', 4 | 8) | ('c', 8 | 16) | ('i' | 'u', 1 | 2 | 4 | 8) => Ok(Self {
+                little_endian,
+                kind,
+                size,
+            }),
+            _ => bail!("unsupported npy dtype `{descr}`, expected a float, complex or integer type"),
+        }
+    }
+
+    /// number of bytes of one element
+    pub(crate) fn size(&self) -> usize {
+        self.size
+    }
+
+    /// the real and (for complex types) imaginary parts of every element of `data`
+    pub(crate) fn decode(&self, data: &[u8]) -> (Vec<f64>, Option<Vec<f64>>) {
+        let elements = data.chunks_exact(self.size);
+
+        if self.kind == 'c' {
+            let part = self.size / 2;
+            let real = elements.clone().map(|value| self.value(&value[..part]));
+            let imaginary = elements.map(|value| self.value(&value[part..]));
+            (real.collect(), Some(imaginary.collect()))
+        } else {
+            (elements.map(|value| self.value(value)).collect(), None)
+        }
+    }
+
+    /// the value of one number (or one part of a complex number) of `bytes.len()` bytes
+    fn value(&self, bytes: &[u8]) -> f64 {
+        let mut buffer = [0; 8];
+        buffer[..bytes.len()].copy_from_slice(bytes);
+        if !self.little_endian {
+            buffer[..bytes.len()].reverse();
+        }
+
+        match (self.kind, bytes.len()) {
+            ('f' | 'c', 4) => f32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64,
+            ('f' | 'c', 8) => f64::from_le_bytes(buffer),
+            ('i', 1) => buffer[0] as i8 as f64,
+            ('i', 2) => i16::from_le_bytes([buffer[0], buffer[1]]) as f64,
+            ('i', 4) => i32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64,
+            ('i', _) => i64::from_le_bytes(buffer) as f64,
+            // unsigned integers, zero padded to eight bytes
+            _ => u64::from_le_bytes(buffer) as f64,
+        }
+    }
+}
+
+/// the value of `key` in the python dictionary literal of an npy header
+fn header_value<'a>(header: &'a str, key: &str) -> Result<&'a str> {
+    let pattern = format!("'{key}':");
+    let start = header
+        .find(&pattern)
+        .with_context(|| format!("npy header has no `{key}`: {header}"))?
+        + pattern.len();
+
+    Ok(header[start..].trim_start())
+}
+
+/// the element type, order and shape of an `.npy` array
+pub(crate) struct Header {
+    dtype: Dtype,
+    fortran_order: bool,
+    pub(crate) shape: Vec<usize>,
+}
+
+/// read the header of an `.npy` array, leaving `reader` at the start of the data
+pub(crate) fn read_header<R: Read>(mut reader: R) -> Result<Header> {
+    let mut preamble = [0; 8];
+    reader
+        .read_exact(&mut preamble)
+        .with_context(|| "failed to read npy header")?;
+
+    if &preamble[..6] != b"\x93NUMPY" {
+        bail!("not an npy file, the magic string is missing");
+    }
+
+    let header_length = match preamble[6] {
+        1 => {
+            let mut length = [0; 2];
+            reader.read_exact(&mut length)?;
+            u16::from_le_bytes(length) as usize
+        }
+        2 | 3 => {
+            let mut length = [0; 4];
+            reader.read_exact(&mut length)?;
+            u32::from_le_bytes(length) as usize
+        }
+        version => bail!("unsupported npy format version {version}"),
+    };
+
+    let mut header = vec![0; header_length];
+    reader
+        .read_exact(&mut header)
+        .with_context(|| "failed to read npy header")?;
+    let header = String::from_utf8_lossy(&header);
+
+    let descr = header_value(&header, "descr")?;
+    let Some(descr) = descr.strip_prefix('\'').and_then(|descr| descr.split('\'').next()) else {
+        bail!("unsupported npy dtype {descr}, structured arrays can not be read");
+    };
+    let dtype = Dtype::parse(descr)?;
+
+    let fortran_order = header_value(&header, "fortran_order")?.starts_with("True");
+
+    let shape = header_value(&header, "shape")?;
+    let shape: Vec<usize> = shape
+        .trim_start_matches('(')
+        .split(')')
+        .next()
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|length| !length.is_empty())
+        .map(str::parse::<usize>)
+        .collect::<Result<_, _>>()
+        .with_context(|| format!("failed to parse the shape in npy header {header}"))?;
+
+    Ok(Header {
+        dtype,
+        fortran_order,
+        shape,
+    })
+}
+
+/// read one `.npy` array
+pub(crate) fn read<R: Read>(mut reader: R) -> Result<Array> {
+    let Header {
+        dtype,
+        fortran_order,
+        shape,
+    } = read_header(&mut reader)?;
+
+    let count: usize = shape.iter().product();
+    let mut data = vec![0; count * dtype.size];
+    reader
+        .read_exact(&mut data)
+        .with_context(|| format!("npy data ended before all {count} values were read"))?;
+
+    let (real, imaginary) = dtype.decode(&data);
+
+    if fortran_order {
+        Ok(Array::from_column_major(shape, real, imaginary))
+    } else {
+        Ok(Array {
+            shape,
+            real,
+            imaginary,
+        })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// an `.npy` file of `data` with the header `descr`, `fortran_order` and `shape`
+    pub(crate) fn file(descr: &str, fortran_order: bool, shape: &[usize], data: &[u8]) -> Vec<u8> {
+        let shape = match shape {
+            [length] => format!("({length},)"),
+            _ => format!(
+                "({})",
+                shape
+                    .iter()
+                    .map(usize::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        };
+        let order = if fortran_order { "True" } else { "False" };
+
+        with_header(
+            &format!("{{'descr': '{descr}', 'fortran_order': {order}, 'shape': {shape}, }}"),
+            data,
+        )
+    }
+
+    /// an `.npy` file of `data` with the python dictionary literal `header`
+    fn with_header(header: &str, data: &[u8]) -> Vec<u8> {
+        let mut header = header.to_string();
+        // numpy pads the header with spaces so the data is aligned to 64 bytes
+        let padding = (64 - (10 + header.len() + 1) % 64) % 64;
+        header.push_str(&" ".repeat(padding));
+        header.push('\n');
+
+        let mut file = b"\x93NUMPY\x01\x00".to_vec();
+        file.extend((header.len() as u16).to_le_bytes());
+        file.extend(header.as_bytes());
+        file.extend(data);
+        file
+    }
+
+    pub(crate) fn doubles(values: &[f64]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn dtypes_and_byte_orders() {
+        let decode = |descr: &str, data: &[u8]| Dtype::parse(descr).unwrap().decode(data).0;
+
+        assert_eq!(decode("<f8", &1.5f64.to_le_bytes()), [1.5]);
+        assert_eq!(decode(">f4", &(-2.25f32).to_be_bytes()), [-2.25]);
+        assert_eq!(decode("<i2", &(-300i16).to_le_bytes()), [-300.]);
+        assert_eq!(decode(">i8", &(-7i64).to_be_bytes()), [-7.]);
+        assert_eq!(decode("|u1", &[200, 3]), [200., 3.]);
+        assert_eq!(decode("=u4", &70000u32.to_le_bytes()), [70000.]);
+
+        for descr in ["<U10", "|O", "|b1", "<f2", "f8"] {
+            assert!(Dtype::parse(descr).is_err(), "{descr}");
+        }
+    }
+
+    #[test]
+    fn row_and_column_major_arrays() {
+        let values = doubles(&[0., 1., 2., 10., 11., 12.]);
+
+        let array = read(&file("<f8", false, &[2, 3], &values)[..]).unwrap();
+        assert_eq!(array.shape, [2, 3]);
+        assert_eq!(array.real, [0., 1., 2., 10., 11., 12.]);
+        assert!(array.imaginary.is_none());
+
+        // the same values in fortran order are the columns of the array
+        let array = read(&file("<f8", true, &[2, 3], &values)[..]).unwrap();
+        assert_eq!(array.shape, [2, 3]);
+        assert_eq!(array.real, [0., 2., 11., 1., 10., 12.]);
+    }
+
+    #[test]
+    fn complex_arrays() {
+        let data: Vec<u8> = [(1., -1.), (2.5, 0.5)]
+            .iter()
+            .flat_map(|(re, im): &(f64, f64)| [re.to_be_bytes(), im.to_be_bytes()].concat())
+            .collect();
+
+        let array = read(&file(">c16", false, &[2], &data)[..]).unwrap();
+        assert_eq!(array.real, [1., 2.5]);
+        assert_eq!(array.imaginary.unwrap(), [-1., 0.5]);
+    }
+
+    #[test]
+    fn truncated_and_structured_arrays_are_rejected() {
+        let mut truncated = file("<f8", false, &[3], &doubles(&[0., 1., 2.]));
+        truncated.truncate(truncated.len() - 1);
+        let error = read(&truncated[..]).err().unwrap();
+        assert!(error.to_string().contains("ended before"), "{error}");
+
+        let structured = with_header(
+            "{'descr': [('a', '<f8')], 'fortran_order': False, 'shape': (1,), }",
+            &doubles(&[1.]),
+        );
+        let error = read(&structured[..]).err().unwrap();
+        assert!(error.to_string().contains("structured"), "{error}");
+    }
+}
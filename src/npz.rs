@@ -0,0 +1,152 @@
+//! reader for NumPy `.npz` archives of coordinate vectors and field arrays
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::io::{Read, Seek};
+use std::path::Path;
+
+use crate::arrays::{self, Array, Arrays};
+use crate::grid;
+use crate::npy;
+use crate::schema::Schema;
+use crate::RawDataset;
+
+/// the arrays of an open `.npz` archive
+struct Archive<R> {
+    zip: zip::ZipArchive<R>,
+    /// the names of the arrays in the archive
+    names: Vec<String>,
+    /// the number of values in each array, so that single values such as a Reynolds number
+    /// saved alongside the fields are not detected as fields
+    counts: HashMap<String, usize>,
+    path: String,
+}
+
+impl Archive<std::fs::File> {
+    fn open(path: &Path) -> Result<Self> {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("failed to open npz file at {}", path.display()))?;
+
+        Self::new(file, path)
+    }
+}
+
+impl<R: Read + Seek> Archive<R> {
+    fn new(reader: R, path: &Path) -> Result<Self> {
+        let mut zip = zip::ZipArchive::new(reader)
+            .with_context(|| format!("{} is not a valid npz archive", path.display()))?;
+
+        let mut names = Vec::new();
+        let mut counts = HashMap::new();
+
+        // entries are read by index, since `file_names` does not keep the order of the archive
+        for index in 0..zip.len() {
+            let file = zip.by_index(index)?;
+            let Some(name) = file.name().strip_suffix(".npy").map(String::from) else {
+                continue;
+            };
+
+            let header = npy::read_header(file).with_context(|| {
+                format!("failed to read array `{name}` of {}", path.display())
+            })?;
+
+            counts.insert(name.clone(), header.shape.iter().product());
+            names.push(name);
+        }
+
+        Ok(Self {
+            zip,
+            names,
+            counts,
+            path: path.display().to_string(),
+        })
+    }
+}
+
+impl<R: Read + Seek> Arrays for Archive<R> {
+    fn description(&self) -> String {
+        format!("the arrays of {}", self.path)
+    }
+
+    fn names(&self) -> Vec<&str> {
+        self.names
+            .iter()
+            .filter(|name| self.counts[*name] != 1)
+            .map(String::as_str)
+            .collect()
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        self.counts.contains_key(name)
+    }
+
+    fn array(&mut self, name: &str) -> Result<Array> {
+        let file = self.zip.by_name(&format!("{name}.npy")).with_context(|| {
+            let available = self.names.join(", ");
+            format!(
+                "array `{name}` is not in {} (available arrays: {available})",
+                self.path
+            )
+        })?;
+
+        npy::read(file).with_context(|| format!("failed to read array `{name}` of {}", self.path))
+    }
+}
+
+/// read the grid and every field of the schema from an `.npz` archive
+pub(crate) fn read(path: &Path, schema: Schema, tolerance: grid::Tolerance) -> Result<RawDataset> {
+    let mut archive = Archive::open(path)?;
+
+    arrays::read(&mut archive, schema, tolerance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npy::tests::{doubles, file};
+    use std::io::{Cursor, Write};
+
+    fn archive(arrays: &[(&str, Vec<u8>)]) -> Archive<Cursor<Vec<u8>>> {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, contents) in arrays {
+            zip.start_file(format!("{name}.npy"), Default::default())
+                .unwrap();
+            zip.write_all(contents).unwrap();
+        }
+        let mut reader = zip.finish().unwrap();
+        reader.set_position(0);
+
+        Archive::new(reader, Path::new("mode.npz")).unwrap()
+    }
+
+    #[test]
+    fn planar_meshgrids_with_single_values() {
+        // x and y from numpy.meshgrid(x, y, indexing="ij") on a 3 x 2 grid, with no z
+        let x = doubles(&[0., 0., 1., 1., 2., 2.]);
+        let y = doubles(&[0., 5., 0., 5., 0., 5.]);
+        let p: Vec<u8> = (0..6)
+            .flat_map(|value| [(value as f64).to_le_bytes(), (-1f64).to_le_bytes()].concat())
+            .collect();
+
+        let mut archive = archive(&[
+            ("x", file("<f8", false, &[3, 2], &x)),
+            ("y", file("<f8", false, &[3, 2], &y)),
+            ("Re", file("<f8", false, &[], &doubles(&[1000.]))),
+            ("p", file("<c16", false, &[3, 2], &p)),
+        ]);
+        assert_eq!(archive.names(), ["x", "y", "p"]);
+        assert!(archive.contains("Re"));
+
+        let raw =
+            arrays::read(&mut archive, Schema::default(), grid::Tolerance::default()).unwrap();
+        assert_eq!(raw.axes.x.values, [0., 1., 2.]);
+        assert_eq!(raw.axes.y.values, [0., 5.]);
+        assert_eq!(raw.axes.z.values, [0.]);
+
+        assert_eq!(raw.fields.len(), 1);
+        let p = &raw.fields[0];
+        assert_eq!(p.name, "p");
+        assert_eq!(p.real[[0, 2, 1, 0]], 5.);
+        assert_eq!(p.imaginary.as_ref().unwrap()[[0, 2, 1, 0]], -1.);
+    }
+}
@@ -0,0 +1,472 @@
+//! point samples read row by row from a CSV file and placed onto the grid
+
+use anyhow::{bail, Context, Result};
+use ndarray::Array4;
+
+use crate::fields::Field;
+use crate::grid;
+use crate::schema::Schema;
+
+/// every row of the CSV, stored column by column so that each column can be released as soon as
+/// it has been moved onto the grid
+pub(crate) struct Samples {
+    x: Vec<f64>,
+    y: Vec<f64>,
+    z: Vec<f64>,
+    /// the columns of every field of the schema, in schema order
+    columns: Vec<Vec<f64>>,
+    /// the schema the columns were read with, including any fields detected from the header
+    schema: Schema,
+    /// value of the time column of the schema, if it has one
+    pub(crate) time: Option<f64>,
+}
+
+/// find the index of every requested column in the CSV header
+fn column_indices(headers: &csv::StringRecord, names: &[&str]) -> Result<Vec<usize>> {
+    names
+        .iter()
+        .map(|name| {
+            headers
+                .iter()
+                .position(|header| header.trim() == *name)
+                .with_context(|| {
+                    let available = headers.iter().collect::<Vec<_>>().join(", ");
+                    format!("column `{name}` is not in the CSV header (available columns: {available})")
+                })
+        })
+        .collect()
+}
+
+/// read every row of the CSV in a single pass, collecting the distinct coordinates along each
+/// direction as we go. If the schema has no fields they are detected from the CSV header
+pub(crate) fn determine_spans<R: std::io::Read>(
+    reader: R,
+    mut schema: Schema,
+    tolerance: grid::Tolerance,
+) -> Result<(grid::Axes, Samples)> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(true)
+        .from_reader(reader);
+
+    let headers = reader
+        .headers()
+        .with_context(|| "failed to read CSV header")?
+        .clone();
+
+    if schema.fields.is_empty() {
+        schema.detect_fields(&headers.iter().collect::<Vec<_>>());
+        println!("detected fields from CSV header: {}", schema.describe());
+    }
+    schema.validate()?;
+
+    let coordinates = &schema.coordinates;
+    let coordinate_columns = column_indices(
+        &headers,
+        &[&coordinates.x, &coordinates.y, &coordinates.z],
+    )?;
+
+    let names = schema.field_columns();
+    let field_columns = column_indices(&headers, &names)?;
+    let names: Vec<String> = names.into_iter().map(String::from).collect();
+    let coordinates = coordinates.clone();
+
+    let time_column = match &schema.time {
+        Some(name) => Some((name.clone(), column_indices(&headers, &[name])?[0])),
+        None => None,
+    };
+    let mut time_varies = false;
+
+    let mut x = grid::AxisCollector::new();
+    let mut y = grid::AxisCollector::new();
+    let mut z = grid::AxisCollector::new();
+
+    let mut samples = Samples {
+        x: Vec::new(),
+        y: Vec::new(),
+        z: Vec::new(),
+        columns: vec![Vec::new(); field_columns.len()],
+        schema,
+        time: None,
+    };
+
+    let parse = |record: &csv::StringRecord, column: usize, name: &str, line: usize| {
+        let text = record.get(column).unwrap_or_default().trim();
+        text.parse::<f64>()
+            .with_context(|| format!("failed to parse `{text}` in column `{name}` on row {line} of csv"))
+    };
+
+    for (idx, record) in reader.records().enumerate() {
+        let line = idx + 2;
+        let record = record.with_context(|| format!("failed to read row {line} of csv"))?;
+
+        let px = parse(&record, coordinate_columns[0], &coordinates.x, line)?;
+        let py = parse(&record, coordinate_columns[1], &coordinates.y, line)?;
+        let pz = parse(&record, coordinate_columns[2], &coordinates.z, line)?;
+
+        if !(px.is_finite() && py.is_finite() && pz.is_finite()) {
+            bail!("row {line} of csv has a non-finite coordinate ({px}, {py}, {pz})");
+        }
+
+        x.push(px);
+        y.push(py);
+        z.push(pz);
+
+        samples.x.push(px);
+        samples.y.push(py);
+        samples.z.push(pz);
+
+        for ((values, column), name) in samples.columns.iter_mut().zip(&field_columns).zip(&names) {
+            values.push(parse(&record, *column, name, line)?);
+        }
+
+        if let Some((name, column)) = &time_column {
+            let time = parse(&record, *column, name, line)?;
+            match samples.time {
+                Some(first) => time_varies |= !tolerance.same(first, time),
+                None => samples.time = Some(time),
+            }
+        }
+    }
+
+    if samples.x.is_empty() {
+        bail!("CSV has no data rows, only a header");
+    }
+
+    if time_varies {
+        println!(
+            "warning: time column varies within the file, using the value on the first row ({})",
+            samples.time.unwrap_or_default()
+        );
+    }
+
+    let axes = grid::Axes {
+        x: x.finish(tolerance),
+        y: y.finish(tolerance),
+        z: z.finish(tolerance),
+    };
+
+    Ok((axes, samples))
+}
+
+impl Samples {
+    /// samples from whole columns of values named `names`, such as the variables of a Tecplot
+    /// zone, given in the order of the points. If the schema has no fields they are detected from
+    /// the names
+    pub(crate) fn from_columns(
+        names: &[String],
+        columns: Vec<Vec<f64>>,
+        mut schema: Schema,
+        description: &str,
+        tolerance: grid::Tolerance,
+    ) -> Result<Self> {
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+
+        if schema.fields.is_empty() {
+            schema.detect_fields(&names);
+            println!("detected fields from {description}: {}", schema.describe());
+        }
+        schema.validate()?;
+
+        // columns may be used by more than one field, so they are copied rather than moved
+        let column = |name: &str| -> Result<Vec<f64>> {
+            let index = names
+                .iter()
+                .position(|other| other.trim() == name)
+                .with_context(|| {
+                    let available = names.join(", ");
+                    format!("variable `{name}` is not in {description} (available variables: {available})")
+                })?;
+            Ok(columns[index].clone())
+        };
+
+        let coordinates = &schema.coordinates;
+        let x = column(&coordinates.x)?;
+        let y = column(&coordinates.y)?;
+        let z = column(&coordinates.z)?;
+
+        let finite = |point: &usize| x[*point].is_finite() && y[*point].is_finite() && z[*point].is_finite();
+        if let Some(point) = (0..x.len()).find(|point| !finite(point)) {
+            bail!(
+                "point {} of {description} has a non-finite coordinate ({}, {}, {})",
+                point + 1,
+                x[point],
+                y[point],
+                z[point]
+            );
+        }
+
+        let columns = schema
+            .field_columns()
+            .into_iter()
+            .map(column)
+            .collect::<Result<Vec<_>>>()?;
+
+        let time = match &schema.time {
+            Some(name) => {
+                let values = column(name)?;
+                let first = values.first().copied().unwrap_or_default();
+                if values.iter().any(|time| !tolerance.same(first, *time)) {
+                    println!("warning: time variable varies within {description}, using the value at the first point ({first})");
+                }
+                Some(first)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            x,
+            y,
+            z,
+            columns,
+            schema,
+            time,
+        })
+    }
+
+    /// the grid lines of points given in order of their index with `i` varying fastest, if the
+    /// points lie on a rectilinear grid with coordinates increasing along every direction
+    pub(crate) fn rectilinear_axes(
+        &self,
+        [nx, ny, nz]: [usize; 3],
+        tolerance: grid::Tolerance,
+    ) -> Option<grid::Axes> {
+        if self.x.len() != nx * ny * nz {
+            return None;
+        }
+
+        let x: Vec<f64> = (0..nx).map(|i| self.x[i]).collect();
+        let y: Vec<f64> = (0..ny).map(|j| self.y[j * nx]).collect();
+        let z: Vec<f64> = (0..nz).map(|k| self.z[k * nx * ny]).collect();
+
+        let on_grid = (0..self.x.len()).all(|point| {
+            tolerance.same(self.x[point], x[point % nx])
+                && tolerance.same(self.y[point], y[(point / nx) % ny])
+                && tolerance.same(self.z[point], z[point / (nx * ny)])
+        });
+
+        if !on_grid {
+            return None;
+        }
+
+        let axis = |values: Vec<f64>| {
+            let length = values.len();
+            let increasing = values.windows(2).all(|pair| pair[0] < pair[1]);
+            let axis = grid::Axis::from_values(values, tolerance);
+            (increasing && axis.len() == length).then_some(axis)
+        };
+
+        Some(grid::Axes {
+            x: axis(x)?,
+            y: axis(y)?,
+            z: axis(z)?,
+        })
+    }
+
+    /// find where every row belongs on the grid, failing if the rows do not fill it exactly
+    pub(crate) fn place(&self, axes: &grid::Axes) -> Result<Vec<usize>> {
+        place_rows(axes, &self.x, &self.y, &self.z)
+    }
+
+    /// assemble the fields of the schema with the rows at the grid points found by `place`
+    pub(crate) fn into_placed(self, axes: &grid::Axes, placement: &[usize]) -> Vec<Field> {
+        let lengths = [axes.x.len(), axes.y.len(), axes.z.len()];
+
+        assemble(self.columns, &self.schema, placement, lengths)
+    }
+
+    /// assemble the fields of the schema as a list of unconnected points in row order, with
+    /// shape `(components, rows, 1, 1)`. Returns the interleaved `(x, y, z)` position of every
+    /// point
+    pub(crate) fn into_point_cloud(self) -> (Vec<f64>, Vec<Field>) {
+        let rows = self.x.len();
+
+        let points = self
+            .x
+            .iter()
+            .zip(&self.y)
+            .zip(&self.z)
+            .flat_map(|((x, y), z)| [*x, *y, *z])
+            .collect();
+
+        let placement: Vec<usize> = (0..rows).collect();
+
+        (points, assemble(self.columns, &self.schema, &placement, [rows, 1, 1]))
+    }
+
+    /// assemble the fields of a curvilinear grid with `lengths` points along each direction,
+    /// taking the rows in order of their point index with `i` varying fastest. Returns the
+    /// interleaved `(x, y, z)` position of every point in the same order
+    pub(crate) fn into_curvilinear(self, lengths: [usize; 3]) -> Result<(Vec<f64>, Vec<Field>)> {
+        let [nx, ny, nz] = lengths;
+        let rows = self.x.len();
+
+        if rows != nx * ny * nz {
+            bail!(
+                "CSV has {rows} rows but the curvilinear grid has {nx} x {ny} x {nz} = {} points",
+                nx * ny * nz
+            );
+        }
+
+        let points = self
+            .x
+            .iter()
+            .zip(&self.y)
+            .zip(&self.z)
+            .flat_map(|((x, y), z)| [*x, *y, *z])
+            .collect();
+
+        let placement: Vec<usize> = (0..rows)
+            .map(|row| {
+                let i = row % nx;
+                let j = (row / nx) % ny;
+                let k = row / (nx * ny);
+                (i * ny + j) * nz + k
+            })
+            .collect();
+
+        Ok((points, assemble(self.columns, &self.schema, &placement, lengths)))
+    }
+}
+
+/// move the columns of every field of the schema onto a grid with `lengths` points, where
+/// `placement` holds the linear index of the grid point of every row
+fn assemble(
+    columns: Vec<Vec<f64>>,
+    schema: &Schema,
+    placement: &[usize],
+    [nx, ny, nz]: [usize; 3],
+) -> Vec<Field> {
+    let mut columns = columns.into_iter();
+    let mut next_array = |components: usize| {
+        let mut out = Array4::zeros((components, nx, ny, nz));
+        for component in 0..components {
+            let column = columns.next().expect("one column per field component");
+            scatter(column, placement, component, &mut out);
+        }
+        out
+    };
+
+    schema
+        .fields
+        .iter()
+        .map(|spec| Field {
+            name: spec.name.clone(),
+            real: next_array(spec.components()),
+            imaginary: spec
+                .imaginary
+                .as_ref()
+                .map(|imaginary| next_array(imaginary.len())),
+        })
+        .collect()
+}
+
+/// parse the `NI,NJ,NK` index dimensions of a curvilinear grid
+pub(crate) fn parse_dimensions(dimensions: &str) -> Result<[usize; 3]> {
+    let lengths = dimensions
+        .split(',')
+        .map(|value| value.trim().parse::<usize>())
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("failed to parse grid dimensions `{dimensions}`"))?;
+
+    let [nx, ny, nz] = lengths[..] else {
+        bail!("grid dimensions `{dimensions}` should be three comma separated point counts");
+    };
+
+    if nx == 0 || ny == 0 || nz == 0 {
+        bail!("grid dimensions `{dimensions}` must all be at least one");
+    }
+
+    Ok([nx, ny, nz])
+}
+
+/// find the linear index (into a `(nx, ny, nz)` array) of the grid point of every row, checking
+/// that every grid point is given exactly once
+fn place_rows(axes: &grid::Axes, x: &[f64], y: &[f64], z: &[f64]) -> Result<Vec<usize>> {
+    let nx = axes.x.len();
+    let ny = axes.y.len();
+    let nz = axes.z.len();
+
+    let mut placement = Vec::with_capacity(x.len());
+    let mut filled = vec![false; nx * ny * nz];
+    let mut ordering = grid::OrderingDetector::new(nx, ny, nz);
+
+    for (idx, ((&x, &y), &z)) in x.iter().zip(y).zip(z).enumerate() {
+        let line = idx + 2;
+
+        let Some(index) = axes.locate(x, y, z) else {
+            bail!("point ({x}, {y}, {z}) on row {line} of csv does not lie on the grid");
+        };
+        let [i, j, k] = index;
+        let linear = (i * ny + j) * nz + k;
+
+        if filled[linear] {
+            // only search for the first occurrence when we are about to error
+            let previous = placement
+                .iter()
+                .position(|other| *other == linear)
+                .map(|other| other + 2)
+                .unwrap_or_default();
+
+            bail!(
+                "point ({x}, {y}, {z}) appears on both row {previous} and row {line} of csv - unable to decide which value belongs on the grid"
+            );
+        }
+
+        filled[linear] = true;
+        placement.push(linear);
+        ordering.observe(idx, index);
+    }
+
+    let missing = filled.iter().filter(|filled| !**filled).count();
+    if missing != 0 {
+        bail!("CSV does not fill the ({nx},{ny},{nz}) grid: {missing} points have no row - the file may be truncated or the points may not form a rectilinear grid");
+    }
+
+    println!("detected row ordering: {}", ordering.describe());
+
+    Ok(placement)
+}
+
+/// move one column of the CSV into component `component` of `out`
+fn scatter(column: Vec<f64>, placement: &[usize], component: usize, out: &mut Array4<f64>) {
+    let ny = out.shape()[2];
+    let nz = out.shape()[3];
+
+    for (value, linear) in column.into_iter().zip(placement) {
+        let i = linear / (ny * nz);
+        let j = (linear / nz) % ny;
+        let k = linear % nz;
+
+        out[[component, i, j, k]] = value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_only_csv_is_rejected() {
+        let csv = "x,y,z,p\n".as_bytes();
+
+        let error = determine_spans(csv, Schema::default(), grid::Tolerance::default())
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("no data rows"), "{error}");
+    }
+
+    #[test]
+    fn shuffled_rows_are_placed_by_coordinate() {
+        let csv = "x,y,z,p\n1,0,0,10\n0,1,0,1\n0,0,0,0\n1,1,0,11\n".as_bytes();
+
+        let (axes, samples) =
+            determine_spans(csv, Schema::default(), grid::Tolerance::default()).unwrap();
+        assert_eq!(axes.x.values, vec![0., 1.]);
+
+        let placement = samples.place(&axes).unwrap();
+        let fields = samples.into_placed(&axes, &placement);
+        assert_eq!(fields[0].real[[0, 1, 0, 0]], 10.);
+        assert_eq!(fields[0].real[[0, 0, 1, 0]], 1.);
+        assert_eq!(fields[0].real[[0, 1, 1, 0]], 11.);
+    }
+}
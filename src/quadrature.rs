@@ -0,0 +1,139 @@
+//! numerical integration over the (possibly non-uniform) rectilinear grid
+
+use crate::cli::Rule;
+use crate::grid;
+
+/// trapezoidal rule weights along one axis. An axis with a single point has unit weight, so
+/// that planar data is integrated over the remaining directions only
+pub(crate) fn trapezoid_weights(axis: &[f64]) -> Vec<f64> {
+    if axis.len() == 1 {
+        return vec![1.];
+    }
+
+    let mut weights = vec![0.; axis.len()];
+
+    for (i, pair) in axis.windows(2).enumerate() {
+        let h = pair[1] - pair[0];
+        weights[i] += h / 2.;
+        weights[i + 1] += h / 2.;
+    }
+
+    weights
+}
+
+/// composite Simpson's rule weights along one (possibly non-uniform) axis
+///
+/// pairs of intervals are integrated with the quadratic through their three points. With an odd
+/// number of intervals the last interval is integrated with the quadratic through the last three
+/// points. Axes with one or two points fall back to the trapezoidal rule
+pub(crate) fn simpson_weights(axis: &[f64]) -> Vec<f64> {
+    let n = axis.len();
+
+    if n < 3 {
+        return trapezoid_weights(axis);
+    }
+
+    let mut weights = vec![0.; n];
+    let intervals = n - 1;
+
+    for start in (0..intervals - intervals % 2).step_by(2) {
+        let h0 = axis[start + 1] - axis[start];
+        let h1 = axis[start + 2] - axis[start + 1];
+        let width = h0 + h1;
+
+        weights[start] += width / 6. * (2. - h1 / h0);
+        weights[start + 1] += width.powi(3) / (6. * h0 * h1);
+        weights[start + 2] += width / 6. * (2. - h0 / h1);
+    }
+
+    if intervals % 2 == 1 {
+        let h0 = axis[n - 2] - axis[n - 3];
+        let h1 = axis[n - 1] - axis[n - 2];
+
+        weights[n - 1] += (2. * h1 * h1 + 3. * h0 * h1) / (6. * (h0 + h1));
+        weights[n - 2] += (h1 * h1 + 3. * h0 * h1) / (6. * h0);
+        weights[n - 3] -= h1.powi(3) / (6. * h0 * (h0 + h1));
+    }
+
+    weights
+}
+
+/// quadrature weights along each direction of the grid
+pub(crate) struct Weights {
+    x: Vec<f64>,
+    y: Vec<f64>,
+    z: Vec<f64>,
+}
+
+impl Weights {
+    pub(crate) fn new(axes: &grid::Axes, rule: Rule) -> Self {
+        let weights = match rule {
+            Rule::Trapezoid => trapezoid_weights,
+            Rule::Simpson => simpson_weights,
+        };
+
+        Self {
+            x: weights(&axes.x.values),
+            y: weights(&axes.y.values),
+            z: weights(&axes.z.values),
+        }
+    }
+
+    pub(crate) fn trapezoid(axes: &grid::Axes) -> Self {
+        Self::new(axes, Rule::Trapezoid)
+    }
+
+    /// integrate `f(i, j, k)` over the grid
+    pub(crate) fn integrate(&self, f: impl Fn(usize, usize, usize) -> f64) -> f64 {
+        let mut total = 0.;
+
+        for (i, wx) in self.x.iter().enumerate() {
+            for (j, wy) in self.y.iter().enumerate() {
+                for (k, wz) in self.z.iter().enumerate() {
+                    total += wx * wy * wz * f(i, j, k);
+                }
+            }
+        }
+
+        total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn integrate(weights: &[f64], axis: &[f64], f: impl Fn(f64) -> f64) -> f64 {
+        weights.iter().zip(axis).map(|(w, &x)| w * f(x)).sum()
+    }
+
+    #[test]
+    fn simpson_is_exact_for_quadratics() {
+        let quadratic = |x: f64| 3. * x * x - 2. * x + 0.5;
+        let antiderivative = |x: f64| x.powi(3) - x * x + 0.5 * x;
+
+        // four (even) and five (odd) non-uniform intervals
+        for axis in [
+            &[-1., -0.7, 0., 0.2, 1.1][..],
+            &[-1., -0.7, 0., 0.2, 1.1, 1.5][..],
+        ] {
+            let exact = antiderivative(axis[axis.len() - 1]) - antiderivative(axis[0]);
+            let integral = integrate(&simpson_weights(axis), axis, quadratic);
+
+            assert!(
+                (integral - exact).abs() < 1e-12,
+                "{} intervals: {integral} != {exact}",
+                axis.len() - 1
+            );
+        }
+    }
+
+    #[test]
+    fn trapezoid_is_exact_for_lines() {
+        let axis = [0., 0.3, 0.4, 1.];
+        let integral = integrate(&trapezoid_weights(&axis), &axis, |x| 2. * x + 1.);
+
+        assert!((integral - 2.).abs() < 1e-12);
+        assert_eq!(trapezoid_weights(&[0.5]), vec![1.]);
+    }
+}
@@ -0,0 +1,475 @@
+//! mapping from the columns of an input file to the fields written to the output
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::path::Path;
+
+use crate::cli;
+
+/// which columns hold the point coordinates and which columns make up each output field
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Schema {
+    #[serde(default)]
+    pub(crate) coordinates: Coordinates,
+    #[serde(default, rename = "field")]
+    pub(crate) fields: Vec<FieldSpec>,
+    /// column holding the time of the snapshot, constant over the file
+    #[serde(default)]
+    pub(crate) time: Option<String>,
+}
+
+/// column names of the point coordinates
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Coordinates {
+    pub(crate) x: String,
+    pub(crate) y: String,
+    pub(crate) z: String,
+}
+
+impl Default for Coordinates {
+    fn default() -> Self {
+        Self {
+            x: "x".into(),
+            y: "y".into(),
+            z: "z".into(),
+        }
+    }
+}
+
+/// a scalar or vector output field, complex if it has imaginary columns
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct FieldSpec {
+    pub(crate) name: String,
+    /// one column for a scalar field, three for a vector field
+    pub(crate) real: Vec<String>,
+    /// columns of the imaginary part, matching `real` in length
+    #[serde(default)]
+    pub(crate) imaginary: Option<Vec<String>>,
+}
+
+impl FieldSpec {
+    pub(crate) fn components(&self) -> usize {
+        self.real.len()
+    }
+
+    /// every column this field is read from, real part first
+    pub(crate) fn columns(&self) -> impl Iterator<Item = &str> {
+        self.real
+            .iter()
+            .chain(self.imaginary.iter().flatten())
+            .map(String::as_str)
+    }
+}
+
+impl Schema {
+    /// read a schema from a `.toml` or `.json` file
+    pub(crate) fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read schema file at {}", path.display()))?;
+
+        let schema = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => serde_json::from_str(&contents)
+                .with_context(|| format!("failed to parse JSON schema {}", path.display()))?,
+            _ => toml::from_str(&contents)
+                .with_context(|| format!("failed to parse TOML schema {}", path.display()))?,
+        };
+
+        Ok(schema)
+    }
+
+    /// build the schema from the command line: a schema file if one is given, extended with any
+    /// fields given as flags. Without either, the fields are left empty to be detected from the
+    /// header of the input with [`Schema::detect_fields`]
+    pub(crate) fn from_args(args: &cli::Args) -> Result<Self> {
+        let mut flag_fields = Vec::new();
+
+        for definition in &args.scalar {
+            let (name, columns) = split_definition(definition)?;
+            flag_fields.push(FieldSpec {
+                name,
+                real: split_columns(columns, 1, definition)?,
+                imaginary: None,
+            });
+        }
+
+        for definition in &args.vector {
+            let (name, columns) = split_definition(definition)?;
+            flag_fields.push(FieldSpec {
+                name,
+                real: split_columns(columns, 3, definition)?,
+                imaginary: None,
+            });
+        }
+
+        for definition in &args.complex_scalar {
+            let (name, columns) = split_definition(definition)?;
+            let mut columns = split_columns(columns, 2, definition)?;
+            let imaginary = columns.split_off(1);
+            flag_fields.push(FieldSpec {
+                name,
+                real: columns,
+                imaginary: Some(imaginary),
+            });
+        }
+
+        for definition in &args.complex_vector {
+            let (name, columns) = split_definition(definition)?;
+            let Some((real, imaginary)) = columns.split_once(':') else {
+                bail!("complex vector `{definition}` should be of the form NAME=R1,R2,R3:I1,I2,I3");
+            };
+            flag_fields.push(FieldSpec {
+                name,
+                real: split_columns(real, 3, definition)?,
+                imaginary: Some(split_columns(imaginary, 3, definition)?),
+            });
+        }
+
+        let mut schema = match &args.schema {
+            Some(path) => Self::load(path)?,
+            None => Self::default(),
+        };
+
+        schema.fields.extend(flag_fields);
+
+        if let Some(time) = &args.time_column {
+            schema.time = Some(time.clone());
+        }
+
+        if let Some(coordinates) = &args.coordinate_columns {
+            let mut columns = split_columns(coordinates, 3, coordinates)?.into_iter();
+            schema.coordinates = Coordinates {
+                x: columns.next().unwrap(),
+                y: columns.next().unwrap(),
+                z: columns.next().unwrap(),
+            };
+        }
+
+        Ok(schema)
+    }
+
+    /// infer the fields from the column names of the input
+    ///
+    /// columns are grouped into complex vectors (`u1r,u2r,u3r` with `u1i,u2i,u3i`), complex
+    /// scalars (`p_r` with `p_i`) and real vectors (`u1,u2,u3` or `u_x,u_y,u_z`). Every column
+    /// that is not part of a complete group is written as a real scalar under its own name. The
+    /// velocity of the original fixed `u1r..w3i` layout keeps its legacy name `velocity`
+    pub(crate) fn detect_fields(&mut self, headers: &[&str]) {
+        let coordinates = [&self.coordinates.x, &self.coordinates.y, &self.coordinates.z];
+        let time = self.time.as_deref();
+
+        let columns: Vec<ParsedColumn> = headers
+            .iter()
+            .map(|header| header.trim())
+            .filter(|header| !header.is_empty())
+            .filter(|header| !coordinates.iter().any(|coordinate| coordinate == header))
+            .filter(|header| time != Some(*header))
+            .map(ParsedColumn::new)
+            .collect();
+
+        let mut used = vec![false; columns.len()];
+        // the fields along with the position of their first column, so the output follows the
+        // order of the input
+        let mut fields: Vec<(usize, FieldSpec)> = Vec::new();
+
+        let find = |base: &str, component: Option<usize>, part: Option<Part>| {
+            columns.iter().position(|column| {
+                column.base == base && column.component == component && column.part == part
+            })
+        };
+
+        let mut bases: Vec<&str> = Vec::new();
+        for column in &columns {
+            if !bases.contains(&column.base) {
+                bases.push(column.base);
+            }
+        }
+
+        for base in bases {
+            let vector = |part| (0..3).map(|c| find(base, Some(c), part)).collect::<Option<Vec<_>>>();
+            let scalar = |part| find(base, None, part).map(|idx| vec![idx]);
+
+            let groups = [
+                (vector(Some(Part::Real)), vector(Some(Part::Imaginary))),
+                (scalar(Some(Part::Real)), scalar(Some(Part::Imaginary))),
+                (vector(None), Some(Vec::new())),
+            ];
+
+            for (real, imaginary) in groups {
+                let (Some(real), Some(imaginary)) = (real, imaginary) else {
+                    continue;
+                };
+
+                if real.iter().chain(&imaginary).any(|idx| used[*idx]) {
+                    continue;
+                }
+
+                for idx in real.iter().chain(&imaginary) {
+                    used[*idx] = true;
+                }
+
+                let names = |indices: &[usize]| -> Vec<String> {
+                    indices.iter().map(|idx| columns[*idx].name.to_string()).collect()
+                };
+
+                fields.push((
+                    real[0],
+                    FieldSpec {
+                        name: base.to_string(),
+                        real: names(&real),
+                        imaginary: (!imaginary.is_empty()).then(|| names(&imaginary)),
+                    },
+                ));
+            }
+        }
+
+        for (idx, column) in columns.iter().enumerate() {
+            if !used[idx] {
+                fields.push((
+                    idx,
+                    FieldSpec {
+                        name: column.name.to_string(),
+                        real: vec![column.name.to_string()],
+                        imaginary: None,
+                    },
+                ));
+            }
+        }
+
+        fields.sort_by_key(|(first_column, _)| *first_column);
+        self.fields = fields.into_iter().map(|(_, field)| field).collect();
+
+        let legacy = columns.len() == LEGACY_COLUMNS.len()
+            && columns
+                .iter()
+                .all(|column| LEGACY_COLUMNS.contains(&column.name));
+
+        if legacy {
+            for field in self.fields.iter_mut().filter(|field| field.name == "u") {
+                field.name = LEGACY_VELOCITY.into();
+            }
+        }
+    }
+
+    /// one line summary of every field, for printing
+    pub(crate) fn describe(&self) -> String {
+        self.fields
+            .iter()
+            .map(|field| {
+                let kind = kind(field.components(), field.imaginary.is_some());
+                format!("{} ({kind})", field.name)
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// check that every field is a scalar or 3-vector with matching real and imaginary parts
+    pub(crate) fn validate(&self) -> Result<()> {
+        if self.fields.is_empty() {
+            bail!("schema does not contain any fields to write");
+        }
+
+        for (idx, field) in self.fields.iter().enumerate() {
+            if field.name.is_empty() {
+                bail!("field {} of the schema has an empty name", idx + 1);
+            }
+
+            if self.fields[..idx].iter().any(|other| other.name == field.name) {
+                bail!("field `{}` appears more than once in the schema", field.name);
+            }
+
+            if !matches!(field.components(), 1 | 3) {
+                bail!(
+                    "field `{}` has {} real columns, expected 1 (scalar) or 3 (vector)",
+                    field.name,
+                    field.components()
+                );
+            }
+
+            if let Some(imaginary) = &field.imaginary {
+                if imaginary.len() != field.components() {
+                    bail!(
+                        "field `{}` has {} real columns but {} imaginary columns",
+                        field.name,
+                        field.components(),
+                        imaginary.len()
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// every field column in the order the fields are listed
+    pub(crate) fn field_columns(&self) -> Vec<&str> {
+        self.fields.iter().flat_map(FieldSpec::columns).collect()
+    }
+}
+
+/// the field columns of the original fixed CSV layout
+const LEGACY_COLUMNS: [&str; 12] = [
+    "u1r", "u2r", "u3r", "u1i", "u2i", "u3i", "w1r", "w2r", "w3r", "w1i", "w2i", "w3i",
+];
+
+/// name of the velocity field of the original fixed CSV layout, which is written as
+/// `real_velocity`, `imaginary_velocity` and `total_velocity_magnitude` as it always has been
+pub(crate) const LEGACY_VELOCITY: &str = "velocity";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Part {
+    Real,
+    Imaginary,
+}
+
+/// suffixes that mark the real or imaginary part of a column, checked in order
+const PART_SUFFIXES: [(&str, Part); 6] = [
+    ("_real", Part::Real),
+    ("_imag", Part::Imaginary),
+    ("_re", Part::Real),
+    ("_im", Part::Imaginary),
+    ("_r", Part::Real),
+    ("_i", Part::Imaginary),
+];
+
+/// suffixes that mark the vector component of a column, checked in order
+const COMPONENT_SUFFIXES: [(&str, usize); 9] = [
+    ("_1", 0),
+    ("_2", 1),
+    ("_3", 2),
+    ("_x", 0),
+    ("_y", 1),
+    ("_z", 2),
+    ("1", 0),
+    ("2", 1),
+    ("3", 2),
+];
+
+/// a column name split into its base name, vector component and complex part
+struct ParsedColumn<'a> {
+    name: &'a str,
+    base: &'a str,
+    component: Option<usize>,
+    part: Option<Part>,
+}
+
+impl<'a> ParsedColumn<'a> {
+    fn new(name: &'a str) -> Self {
+        let mut rest = name;
+
+        let mut part = strip_suffix(&mut rest, &PART_SUFFIXES);
+
+        // a bare `r` or `i` is only taken as the complex part directly after a component
+        // number, as in `u1r`, so that names like `phi` are left alone
+        if part.is_none() {
+            for (suffix, marker) in [("r", Part::Real), ("i", Part::Imaginary)] {
+                if let Some(stripped) = rest.strip_suffix(suffix) {
+                    if stripped.len() > 1 && stripped.ends_with(['1', '2', '3']) {
+                        rest = stripped;
+                        part = Some(marker);
+                        break;
+                    }
+                }
+            }
+        }
+
+        let component = strip_suffix(&mut rest, &COMPONENT_SUFFIXES);
+
+        Self {
+            name,
+            base: rest,
+            component,
+            part,
+        }
+    }
+}
+
+/// remove the first matching suffix from `rest`, as long as something is left over
+fn strip_suffix<T: Copy>(rest: &mut &str, suffixes: &[(&str, T)]) -> Option<T> {
+    for (suffix, marker) in suffixes {
+        if let Some(stripped) = rest.strip_suffix(suffix) {
+            if !stripped.is_empty() {
+                *rest = stripped;
+                return Some(*marker);
+            }
+        }
+    }
+
+    None
+}
+
+/// split a `NAME=COLUMNS` command line definition
+fn split_definition(definition: &str) -> Result<(String, &str)> {
+    match definition.split_once('=') {
+        Some((name, columns)) if !name.trim().is_empty() => Ok((name.trim().to_string(), columns)),
+        _ => bail!("field definition `{definition}` should be of the form NAME=COLUMNS"),
+    }
+}
+
+/// split a comma separated list of exactly `expected` column names
+fn split_columns(columns: &str, expected: usize, definition: &str) -> Result<Vec<String>> {
+    let columns: Vec<String> = columns
+        .split(',')
+        .map(|column| column.trim().to_string())
+        .collect();
+
+    if columns.len() != expected || columns.iter().any(String::is_empty) {
+        bail!(
+            "expected {expected} comma separated column names in `{definition}`, found {}",
+            columns.len()
+        );
+    }
+
+    Ok(columns)
+}
+
+/// the kind of a field with `components` components, for printing
+pub(crate) fn kind(components: usize, complex: bool) -> &'static str {
+    match (components, complex) {
+        (1, false) => "scalar",
+        (1, true) => "complex scalar",
+        (_, false) => "vector",
+        (_, true) => "complex vector",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect(headers: &[&str]) -> Schema {
+        let mut schema = Schema::default();
+        schema.detect_fields(headers);
+        schema
+    }
+
+    #[test]
+    fn legacy_layout_keeps_velocity_name() {
+        let headers = [
+            "x", "y", "z", "u1r", "u2r", "u3r", "u1i", "u2i", "u3i", "w1r", "w2r", "w3r", "w1i",
+            "w2i", "w3i",
+        ];
+
+        let schema = detect(&headers);
+        assert_eq!(
+            schema.describe(),
+            "velocity (complex vector), w (complex vector)"
+        );
+        assert_eq!(schema.fields[0].real, ["u1r", "u2r", "u3r"]);
+    }
+
+    #[test]
+    fn other_layouts_are_grouped_by_name() {
+        let headers = [
+            "x", "y", "z", "u1r", "u2r", "u3r", "u1i", "u2i", "u3i", "p_r", "p_i", "T",
+        ];
+
+        let schema = detect(&headers);
+        assert_eq!(
+            schema.describe(),
+            "u (complex vector), p (complex scalar), T (scalar)"
+        );
+    }
+}
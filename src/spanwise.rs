@@ -0,0 +1,165 @@
+//! reconstruction of 3D fields from 2D (BiGlobal) modes with a Fourier spanwise direction
+
+use anyhow::{bail, Result};
+use ndarray::Array4;
+
+use crate::cli;
+use crate::fields::Field;
+use crate::grid;
+
+/// replace the single z value of a planar grid with a spanwise axis and reconstruct every field
+/// along it
+///
+/// complex fields become `f(x, y) e^{iβ(z - z0)}`, where `z0` is the z value of the plane, so the
+/// input plane is reproduced at the start of the axis. Real fields are taken to be independent of
+/// z and are copied to every plane
+pub(crate) fn expand(
+    axes: &mut grid::Axes,
+    fields: &mut [Field],
+    args: &cli::Args,
+    tolerance: grid::Tolerance,
+) -> Result<()> {
+    let Some(beta) = args.spanwise_wavenumber else {
+        return Ok(());
+    };
+
+    if axes.z.len() != 1 {
+        bail!(
+            "--spanwise-wavenumber needs a 2D mode with a single z value, but the grid has {} z values",
+            axes.z.len()
+        );
+    }
+
+    let extent = match args.spanwise_extent {
+        Some(extent) => extent,
+        None if beta != 0. => 2. * std::f64::consts::PI / beta.abs(),
+        None => bail!("--spanwise-extent is required when the spanwise wavenumber is zero"),
+    };
+
+    if !extent.is_finite() || extent <= 0. {
+        bail!("the spanwise extent must be positive and finite, got {extent}");
+    }
+
+    if args.spanwise_points < 2 {
+        bail!(
+            "--spanwise-points must be at least 2, got {}",
+            args.spanwise_points
+        );
+    }
+
+    let nz = args.spanwise_points;
+    let z0 = axes.z.values[0];
+    let offsets: Vec<f64> = (0..nz)
+        .map(|k| extent * k as f64 / (nz - 1) as f64)
+        .collect();
+
+    for field in fields.iter_mut() {
+        let (components, nx, ny, _) = field.real.dim();
+        let mut real = Array4::zeros((components, nx, ny, nz));
+
+        let Some(imaginary) = &field.imaginary else {
+            for k in 0..nz {
+                for v in 0..components {
+                    for i in 0..nx {
+                        for j in 0..ny {
+                            real[[v, i, j, k]] = field.real[[v, i, j, 0]];
+                        }
+                    }
+                }
+            }
+
+            field.real = real;
+            continue;
+        };
+
+        let mut expanded = Array4::zeros((components, nx, ny, nz));
+
+        for (k, offset) in offsets.iter().enumerate() {
+            let (sin, cos) = (beta * offset).sin_cos();
+
+            for v in 0..components {
+                for i in 0..nx {
+                    for j in 0..ny {
+                        let re = field.real[[v, i, j, 0]];
+                        let im = imaginary[[v, i, j, 0]];
+
+                        real[[v, i, j, k]] = re * cos - im * sin;
+                        expanded[[v, i, j, k]] = re * sin + im * cos;
+                    }
+                }
+            }
+        }
+
+        field.real = real;
+        field.imaginary = Some(expanded);
+    }
+
+    axes.z = grid::Axis::from_values(offsets.iter().map(|offset| z0 + offset).collect(), tolerance);
+
+    println!(
+        "expanded along z with β = {beta} over [{z0}, {}] with {nz} points",
+        z0 + extent
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn modes_vary_as_exp_i_beta_z() {
+        let tolerance = grid::Tolerance::default();
+        let mut axes = grid::Axes {
+            x: grid::Axis::from_values(vec![0., 1.], tolerance),
+            y: grid::Axis::from_values(vec![0.], tolerance),
+            z: grid::Axis::from_values(vec![0.5], tolerance),
+        };
+
+        // a complex mode equal to one on the plane, and a real mean flow
+        let mut fields = vec![
+            Field {
+                name: "u".into(),
+                real: Array4::ones((1, 2, 1, 1)),
+                imaginary: Some(Array4::zeros((1, 2, 1, 1))),
+            },
+            Field {
+                name: "mean".into(),
+                real: Array4::from_elem((1, 2, 1, 1), 3.),
+                imaginary: None,
+            },
+        ];
+
+        let args = cli::Args::parse_from([
+            "burak-vtk",
+            "--csv-path",
+            "in.csv",
+            "--output",
+            "out.vtr",
+            "--spanwise-wavenumber",
+            "2",
+            "--spanwise-points",
+            "5",
+        ]);
+
+        expand(&mut axes, &mut fields, &args, tolerance).unwrap();
+
+        // one wavelength 2π/β starting at the plane
+        assert_eq!(axes.z.len(), 5);
+        assert_eq!(axes.z.values[0], 0.5);
+
+        let imaginary = fields[0].imaginary.as_ref().unwrap();
+        for k in [1, 2] {
+            let z = axes.z.values[k];
+            let (sin, cos) = (2. * (z - 0.5)).sin_cos();
+
+            for i in 0..2 {
+                assert!((fields[0].real[[0, i, 0, k]] - cos).abs() < 1e-12);
+                assert!((imaginary[[0, i, 0, k]] - sin).abs() < 1e-12);
+                assert_eq!(fields[1].real[[0, i, 0, k]], 3.);
+            }
+        }
+    }
+}
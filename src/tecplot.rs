@@ -0,0 +1,536 @@
+//! reader for Tecplot ASCII `.dat` files holding a single ordered zone
+//!
+//! the header names the variables and gives the `I`, `J` and `K` dimensions of the zone, so the
+//! points are placed by their index rather than by scanning their coordinates. Zones whose points
+//! lie on a rectilinear grid are written as one, any other zone as a curvilinear grid
+
+use anyhow::{bail, Context, Result};
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use crate::cli::{self, CoordinateSystem};
+use crate::coordinates;
+use crate::grid;
+use crate::input;
+use crate::layout::Layout;
+use crate::points::Samples;
+use crate::schema::Schema;
+use crate::RawDataset;
+
+/// how the values of a zone are ordered
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Packing {
+    /// every variable of a point, one point after another
+    Point,
+    /// every point of a variable, one variable after another
+    Block,
+}
+
+/// a token of a Tecplot header
+#[derive(Debug, PartialEq)]
+enum Token {
+    /// a keyword or unquoted value
+    Word(String),
+    /// a quoted string
+    Text(String),
+    /// a parenthesised list, kept as written
+    List(String),
+    Equals,
+}
+
+impl Token {
+    fn value(&self) -> &str {
+        match self {
+            Token::Word(value) | Token::Text(value) | Token::List(value) => value,
+            Token::Equals => "=",
+        }
+    }
+}
+
+/// split a header into tokens, treating commas as whitespace
+fn tokens(header: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = header.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() || c == ',' => {}
+            '=' => tokens.push(Token::Equals),
+            '"' => {
+                let text: String = chars.by_ref().take_while(|c| *c != '"').collect();
+                tokens.push(Token::Text(text));
+            }
+            '(' => {
+                let list: String = chars.by_ref().take_while(|c| *c != ')').collect();
+                tokens.push(Token::List(list));
+            }
+            _ => {
+                let mut word = String::from(c);
+                while let Some(c) = chars.next_if(|c| !(c.is_whitespace() || ",=\"(".contains(*c)))
+                {
+                    word.push(c);
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+
+    tokens
+}
+
+/// records of the header other than the variables and the zone, which end the list of variables
+const RECORDS: [&str; 5] = ["DATASETAUXDATA", "AUXDATA", "VARAUXDATA", "TEXT", "GEOMETRY"];
+
+/// the variables and dimensions of the zone, from the header of the file
+struct Header {
+    variables: Vec<String>,
+    lengths: [usize; 3],
+    packing: Packing,
+    /// the `SOLUTIONTIME` of the zone
+    time: Option<f64>,
+}
+
+impl Header {
+    fn parse(header: &str) -> Result<Self> {
+        let tokens = tokens(header);
+
+        let mut variables = Vec::new();
+        let mut lengths = [1; 3];
+        let mut packing = Packing::Point;
+        let mut time = None;
+        let mut zones = 0;
+
+        let is_word = |index: usize, words: &[&str]| match tokens.get(index) {
+            Some(Token::Word(word)) => words.iter().any(|w| word.eq_ignore_ascii_case(w)),
+            _ => false,
+        };
+        let is_keyword = |index: usize| {
+            is_word(index, &["ZONE"])
+                || is_word(index, &RECORDS)
+                || tokens.get(index + 1) == Some(&Token::Equals)
+        };
+
+        let mut index = 0;
+        while index < tokens.len() {
+            let token = &tokens[index];
+
+            if is_word(index, &["ZONE"]) {
+                zones += 1;
+                index += 1;
+                continue;
+            }
+
+            // auxiliary data is a single `name="value"` pair, after the variable number for
+            // VARAUXDATA
+            if is_word(index, &["DATASETAUXDATA", "AUXDATA"]) {
+                index += 4;
+                continue;
+            }
+            if is_word(index, &["VARAUXDATA"]) {
+                index += 5;
+                continue;
+            }
+
+            // text and geometry records hold their own options, such as `F=POINT` for the
+            // points of a geometry, which must not be taken for the options of the zone
+            if is_word(index, &["TEXT", "GEOMETRY"]) {
+                index += 1;
+                while index < tokens.len()
+                    && !is_word(index, &["ZONE", "VARIABLES"])
+                    && !is_word(index, &RECORDS)
+                {
+                    index += 1;
+                }
+                continue;
+            }
+
+            if !matches!(token, Token::Word(_)) || tokens.get(index + 1) != Some(&Token::Equals) {
+                bail!("unexpected `{}` in the Tecplot header", token.value());
+            }
+
+            let key = token.value().to_uppercase();
+            index += 2;
+
+            if key == "VARIABLES" {
+                while index < tokens.len() && !is_keyword(index) {
+                    variables.push(tokens[index].value().trim().to_string());
+                    index += 1;
+                }
+                continue;
+            }
+
+            let Some(value) = tokens.get(index) else {
+                bail!("`{key}` has no value in the Tecplot header");
+            };
+            let value = value.value();
+            index += 1;
+
+            let length = |value: &str| {
+                value
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|length| *length > 0)
+                    .with_context(|| {
+                        format!("zone dimension `{key}={value}` should be a positive whole number")
+                    })
+            };
+
+            match key.as_str() {
+                "I" => lengths[0] = length(value)?,
+                "J" => lengths[1] = length(value)?,
+                "K" => lengths[2] = length(value)?,
+                "DATAPACKING" | "F" => {
+                    packing = match value.to_uppercase().as_str() {
+                        "POINT" => Packing::Point,
+                        "BLOCK" => Packing::Block,
+                        _ => bail!("zone packing `{value}` is not supported, only ordered POINT and BLOCK zones can be read"),
+                    }
+                }
+                "ZONETYPE" if !value.eq_ignore_ascii_case("ORDERED") => {
+                    bail!("zone type `{value}` is not supported, only ordered zones can be read");
+                }
+                "VARLOCATION" if value.to_uppercase().contains("CELLCENTERED") => {
+                    bail!("cell centered variables are not supported, every variable must be given at the nodes");
+                }
+                "VARSHARELIST" | "CONNECTIVITYSHAREZONE" | "NODES" | "ELEMENTS" | "N" | "E" => {
+                    bail!("zone option `{key}` is not supported, only ordered zones can be read");
+                }
+                "SOLUTIONTIME" => {
+                    time = Some(value.parse::<f64>().with_context(|| {
+                        format!("failed to parse zone solution time `{value}`")
+                    })?)
+                }
+                // titles, strand ids, data types and the like do not change how the values are read
+                _ => {}
+            }
+        }
+
+        if variables.is_empty() {
+            bail!("Tecplot header has no VARIABLES");
+        }
+
+        if zones == 0 {
+            bail!("Tecplot header has no ZONE");
+        }
+
+        Ok(Self {
+            variables,
+            lengths,
+            packing,
+            time,
+        })
+    }
+}
+
+/// parse a value of the zone, which may use a fortran `D` exponent or repeat a value `count` times
+/// as `count*value`. Returns the count and the value
+fn parse_value(text: &str) -> Option<(usize, f64)> {
+    let (count, value) = match text.split_once('*') {
+        Some((count, value)) => (count.parse::<usize>().ok()?, value),
+        None => (1, text),
+    };
+
+    let value = value
+        .parse::<f64>()
+        .or_else(|_| value.replace(['D', 'd'], "E").parse::<f64>())
+        .ok()?;
+
+    Some((count, value))
+}
+
+/// whether a line of the file starts the values of the zone rather than continuing the header
+fn starts_data(line: &str) -> bool {
+    line.trim_start()
+        .starts_with(|c: char| c.is_ascii_digit() || "+-.".contains(c))
+}
+
+/// the header and the values of every variable of the zone
+fn read_zone<R: BufRead>(reader: R) -> Result<(Header, Vec<Vec<f64>>)> {
+    let mut lines = reader.lines().enumerate();
+
+    let mut text = String::new();
+    let mut first_data = None;
+
+    for (index, line) in lines.by_ref() {
+        let line =
+            line.with_context(|| format!("failed to read line {} of the Tecplot file", index + 1))?;
+
+        // comments are only allowed in the header
+        if line.trim_start().starts_with('#') {
+            continue;
+        }
+
+        if starts_data(&line) {
+            first_data = Some((index, line));
+            break;
+        }
+
+        text.push_str(&line);
+        text.push('\n');
+    }
+
+    let header = Header::parse(&text)?;
+    let [ni, nj, nk] = header.lengths;
+    let points = ni * nj * nk;
+    let variables = header.variables.len();
+    let expected = points * variables;
+
+    let mut columns = vec![Vec::with_capacity(points); variables];
+    let mut count = 0;
+
+    let data = first_data
+        .into_iter()
+        .map(Ok)
+        .chain(lines.map(|(index, line)| line.map(|line| (index, line))));
+
+    for line in data {
+        let (index, line) = line.context("failed to read the values of the Tecplot zone")?;
+        let number = index + 1;
+
+        for text in line
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|text| !text.is_empty())
+        {
+            let Some((repeat, value)) = parse_value(text) else {
+                if text.eq_ignore_ascii_case("ZONE") {
+                    bail!("Tecplot file has more than one zone on line {number}, only single zone files can be read");
+                }
+                bail!("failed to parse `{text}` on line {number} of the Tecplot file");
+            };
+
+            if count + repeat > expected {
+                bail!(
+                    "Tecplot zone has more values than its {ni} x {nj} x {nk} points of {variables} variables on line {number}"
+                );
+            }
+
+            for _ in 0..repeat {
+                let variable = match header.packing {
+                    Packing::Point => count % variables,
+                    Packing::Block => count / points,
+                };
+                columns[variable].push(value);
+                count += 1;
+            }
+        }
+    }
+
+    if count != expected {
+        bail!(
+            "Tecplot zone holds {count} values, but its {ni} x {nj} x {nk} points of {variables} variables need {expected} - the file may be truncated"
+        );
+    }
+
+    Ok((header, columns))
+}
+
+/// place a 2D zone without the `z` coordinate at `z = 0`, since 2D zones usually leave it out
+fn place_2d_zone(header: &mut Header, columns: &mut Vec<Vec<f64>>, z: &str) {
+    let [ni, nj, nk] = header.lengths;
+
+    if nk == 1 && !header.variables.iter().any(|variable| variable == z) {
+        println!("Tecplot zone has no `{z}` variable, placing the 2D zone at {z} = 0");
+        header.variables.push(z.to_string());
+        columns.push(vec![0.; ni * nj]);
+    }
+}
+
+/// read the zone of a Tecplot ASCII file, as a rectilinear grid if its points lie on one and as a
+/// curvilinear grid otherwise
+pub(crate) fn read(
+    path: &Path,
+    schema: Schema,
+    args: &cli::Args,
+    tolerance: grid::Tolerance,
+) -> Result<RawDataset> {
+    let reader = BufReader::new(input::open(path)?);
+
+    let (mut header, mut columns) = read_zone(reader)
+        .with_context(|| format!("failed to read Tecplot file {}", path.display()))?;
+
+    place_2d_zone(&mut header, &mut columns, &schema.coordinates.z);
+
+    let lengths = header.lengths;
+    let [ni, nj, nk] = lengths;
+
+    let description = format!("the variables of {}", path.display());
+    let mut samples =
+        Samples::from_columns(&header.variables, columns, schema, &description, tolerance)?;
+    samples.time = samples.time.or(header.time);
+    let time = samples.time;
+
+    let (axes, fields, layout) = match samples.rectilinear_axes(lengths, tolerance) {
+        Some(axes) => {
+            println!("mesh size is ({ni},{nj},{nk})");
+
+            let (_, fields) = samples.into_curvilinear(lengths)?;
+            (axes, fields, None)
+        }
+        None => {
+            if args.coordinate_system != CoordinateSystem::Cartesian {
+                bail!("the points of {} do not lie on a rectilinear grid, which is needed for --coordinate-system", path.display());
+            }
+
+            println!("zone points do not lie on a rectilinear grid, writing a curvilinear mesh of size ({ni},{nj},{nk})");
+
+            let (points, fields) = samples.into_curvilinear(lengths)?;
+            coordinates::check_indexed(args, &fields, "a curvilinear grid")?;
+
+            let layout = Layout::Curvilinear { points };
+            (
+                grid::Axes::indices(lengths, tolerance),
+                fields,
+                Some(layout),
+            )
+        }
+    };
+
+    Ok(RawDataset {
+        axes,
+        fields,
+        time,
+        layout,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn zone(text: &str) -> Result<(Header, Vec<Vec<f64>>)> {
+        read_zone(Cursor::new(text))
+    }
+
+    fn error(text: &str) -> String {
+        zone(text).err().unwrap().to_string()
+    }
+
+    /// a point packed 3x2x2 zone where `p` at `(i, j, k)` is `100 i + 10 j + k`, with the x
+    /// coordinates written with `D` exponents
+    fn point_zone() -> String {
+        let mut text = String::from(
+            "# written by a solver\nTITLE = \"mode\"\nVARIABLES = \"x\", \"y\"\n\"z\" \"p\"\n\
+             ZONE T=\"a b\", I=3, J=2, K=2, DATAPACKING=POINT, SOLUTIONTIME=2.5\n",
+        );
+
+        for k in 0..2 {
+            for j in 0..2 {
+                for i in 0..3 {
+                    let x = format!("{}D+00", i as f64 * 0.5);
+                    text += &format!("{x} {j} {k} {}\n", 100 * i + 10 * j + k);
+                }
+            }
+        }
+
+        text
+    }
+
+    #[test]
+    fn point_packing() {
+        let (header, columns) = zone(&point_zone()).unwrap();
+
+        assert_eq!(header.variables, ["x", "y", "z", "p"]);
+        assert_eq!(header.lengths, [3, 2, 2]);
+        assert_eq!(header.time, Some(2.5));
+        assert_eq!(columns[0][..3], [0., 0.5, 1.]);
+        // point (2, 1, 1), with i varying fastest
+        assert_eq!(columns[3][2 + 3 * (1 + 2)], 211.);
+    }
+
+    #[test]
+    fn block_packing_and_repeats() {
+        let text = "VARIABLES = X Y P\nZONE I=2 J=2 F=BLOCK\n0 1 0 1\n2*0 2*1\n1, 2, 3, 4.5d-1\n";
+        let (header, columns) = zone(text).unwrap();
+
+        assert_eq!(header.variables, ["X", "Y", "P"]);
+        assert_eq!(header.lengths, [2, 2, 1]);
+        assert_eq!(header.time, None);
+        assert_eq!(
+            columns,
+            [[0., 1., 0., 1.], [0., 0., 1., 1.], [1., 2., 3., 0.45]]
+        );
+    }
+
+    #[test]
+    fn auxiliary_data_and_text_records_are_skipped() {
+        let text = "VARIABLES = x y z p\nDATASETAUXDATA Reynolds=\"1000\"\n\
+                    TEXT X=10, Y=90, F=BLOCK, T=\"mode 1\"\n\
+                    ZONE I=2 J=2\nAUXDATA Re=\"1000\"\nVARAUXDATA 4 units=\"Pa\"\n\
+                    0 0 0 1\n1 0 0 2\n0 1 0 3\n1 1 0 4\n";
+        let (header, columns) = zone(text).unwrap();
+
+        assert_eq!(header.variables, ["x", "y", "z", "p"]);
+        assert_eq!(header.packing, Packing::Point);
+        assert_eq!(columns[3], [1., 2., 3., 4.]);
+    }
+
+    #[test]
+    fn zones_without_z_are_placed_at_zero() {
+        let text = "VARIABLES = x y p\nZONE I=2 J=2\n0 0 1\n1 0 2\n0 1 3\n1 1 4\n";
+        let (mut header, mut columns) = zone(text).unwrap();
+
+        place_2d_zone(&mut header, &mut columns, "z");
+        assert_eq!(header.variables, ["x", "y", "p", "z"]);
+        assert_eq!(columns[3], [0.; 4]);
+
+        // a zone that has a z variable keeps it
+        place_2d_zone(&mut header, &mut columns, "z");
+        assert_eq!(columns.len(), 4);
+    }
+
+    #[test]
+    fn zones_are_placed_on_rectilinear_or_curvilinear_grids() {
+        let (header, columns) = zone(&point_zone()).unwrap();
+        let samples = Samples::from_columns(
+            &header.variables,
+            columns,
+            Schema::default(),
+            "zone",
+            grid::Tolerance::default(),
+        )
+        .unwrap();
+        let axes = samples
+            .rectilinear_axes(header.lengths, grid::Tolerance::default())
+            .unwrap();
+        assert_eq!(axes.x.values, [0., 0.5, 1.]);
+
+        // a sheared zone
+        let text = "VARIABLES = x y z p\nZONE I=2 J=2\n0 0 0 1\n1 0 0 2\n0.5 1 0 3\n1.5 1 0 4\n";
+        let (header, columns) = zone(text).unwrap();
+        let samples = Samples::from_columns(
+            &header.variables,
+            columns,
+            Schema::default(),
+            "zone",
+            grid::Tolerance::default(),
+        )
+        .unwrap();
+        assert!(samples
+            .rectilinear_axes(header.lengths, grid::Tolerance::default())
+            .is_none());
+
+        let (points, fields) = samples.into_curvilinear(header.lengths).unwrap();
+        assert_eq!(points[6..9], [0.5, 1., 0.]);
+        assert_eq!(fields[0].real[[0, 0, 1, 0]], 3.);
+    }
+
+    #[test]
+    fn unsupported_zones_are_rejected() {
+        let finite_element = "VARIABLES = x\nZONE N=3, E=1, ZONETYPE=FETRIANGLE\n1 2 3\n";
+        assert!(error(finite_element).contains("not supported"));
+
+        let cell_centered = "VARIABLES = x p\nZONE I=2, VARLOCATION=([2]=CELLCENTERED)\n0 1 2 3\n";
+        assert!(error(cell_centered).contains("cell centered"));
+
+        let two_zones = "VARIABLES = x\nZONE I=2\n0 1\nZONE I=2\n0 1\n";
+        assert!(error(two_zones).contains("more than one zone"));
+    }
+
+    #[test]
+    fn value_counts_must_match_the_zone() {
+        assert!(error("VARIABLES = x p\nZONE I=3\n0 1 2 3 4\n").contains("may be truncated"));
+        assert!(error("VARIABLES = x\nZONE I=2\n0 1 2\n").contains("more values"));
+        assert!(error("VARIABLES = x\n0 1\n").contains("no ZONE"));
+    }
+}
@@ -0,0 +1,88 @@
+//! sanity checks of the velocity field before it is visualised
+
+use anyhow::Result;
+use ndarray::Array4;
+
+use crate::cli;
+use crate::derivatives::Differentiator;
+use crate::fields::{self, Field};
+use crate::grid;
+use crate::quadrature::Weights;
+use crate::writer::PointArray;
+
+/// report the divergence of the real and imaginary velocity, normalised by the RMS magnitude of
+/// the velocity gradient tensor. An incompressible mode should be divergence free up to the
+/// discretisation error
+///
+/// returns the divergence of each part if `--write-divergence` was given
+pub(crate) fn validate(
+    fields: &[Field],
+    axes: &grid::Axes,
+    args: &cli::Args,
+) -> Result<Vec<PointArray<'static>>> {
+    let velocity = fields::find_vector(fields, &args.velocity_field)?;
+    let differentiator = Differentiator::new(axes, args.fd_order)?;
+    let weights = Weights::trapezoid(axes);
+    let volume = weights.integrate(|_, _, _| 1.);
+
+    let parts = [
+        ("real", Some(&velocity.real)),
+        ("imaginary", velocity.imaginary.as_ref()),
+    ];
+
+    let mut arrays = Vec::new();
+
+    for (part, values) in parts {
+        let Some(values) = values else {
+            continue;
+        };
+
+        let gradient = differentiator.gradient(values);
+
+        let nx = axes.x.len();
+        let ny = axes.y.len();
+        let nz = axes.z.len();
+        let mut divergence: Array4<f64> = Array4::zeros((1, nx, ny, nz));
+
+        for i in 0..nx {
+            for j in 0..ny {
+                for k in 0..nz {
+                    divergence[[0, i, j, k]] = (0..3).map(|a| gradient[[4 * a, i, j, k]]).sum();
+                }
+            }
+        }
+
+        let gradient_scale = (weights
+            .integrate(|i, j, k| (0..9).map(|c| gradient[[c, i, j, k]].powi(2)).sum())
+            / volume)
+            .sqrt();
+        let rms = (weights.integrate(|i, j, k| divergence[[0, i, j, k]].powi(2)) / volume).sqrt();
+        let max = divergence
+            .iter()
+            .fold(0., |max: f64, value| max.max(value.abs()));
+
+        if gradient_scale == 0. {
+            println!("divergence check ({part} part): velocity gradient is zero, max |div u| {max:.3e}");
+        } else {
+            let max = max / gradient_scale;
+            let rms = rms / gradient_scale;
+
+            println!(
+                "divergence check ({part} part): max {max:.3e}, RMS {rms:.3e} (normalised by RMS |grad u| = {gradient_scale:.3e})"
+            );
+
+            if max > args.divergence_tolerance {
+                println!(
+                    "warning: normalised divergence of the {part} part of `{}` exceeds {} - the mode may not be incompressible or the grid may be misread",
+                    args.velocity_field, args.divergence_tolerance
+                );
+            }
+        }
+
+        if args.write_divergence {
+            arrays.push(PointArray::owned(format!("{part}_divergence"), divergence));
+        }
+    }
+
+    Ok(arrays)
+}
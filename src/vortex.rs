@@ -0,0 +1,169 @@
+//! vortex identification criteria from the velocity gradient tensor
+
+use anyhow::Result;
+use ndarray::Array4;
+
+use crate::cli;
+use crate::derivatives::Differentiator;
+use crate::fields::{self, Field};
+use crate::grid;
+use crate::writer::PointArray;
+
+/// Q-criterion and λ2 of the real part of the velocity, and of the physical velocity
+/// `Re(u e^{iφ})` if `--vortex-phase` was given
+pub(crate) fn vortex_criteria(
+    fields: &[Field],
+    axes: &grid::Axes,
+    args: &cli::Args,
+) -> Result<Vec<PointArray<'static>>> {
+    let velocity = fields::find_vector(fields, &args.velocity_field)?;
+    let differentiator = Differentiator::new(axes, args.fd_order)?;
+
+    let (q, lambda2) = criteria(&differentiator.gradient(&velocity.real));
+    let mut arrays = vec![
+        PointArray::owned("q_criterion", q),
+        PointArray::owned("lambda2", lambda2),
+    ];
+
+    if let Some(phase) = args.vortex_phase {
+        let physical = velocity.at_phase(phase);
+        let (q, lambda2) = criteria(&differentiator.gradient(&physical.values));
+
+        arrays.push(PointArray::owned("q_criterion_phase", q));
+        arrays.push(PointArray::owned("lambda2_phase", lambda2));
+    }
+
+    Ok(arrays)
+}
+
+/// Q-criterion `½(|Ω|² - |S|²)` and λ2, the middle eigenvalue of `S² + Ω²`, at every point
+/// from a gradient tensor where component `3 * a + b` is `∂u_a / ∂x_b`
+fn criteria(gradient: &Array4<f64>) -> (Array4<f64>, Array4<f64>) {
+    let nx = gradient.shape()[1];
+    let ny = gradient.shape()[2];
+    let nz = gradient.shape()[3];
+
+    let mut q = Array4::zeros((1, nx, ny, nz));
+    let mut lambda2 = Array4::zeros((1, nx, ny, nz));
+
+    for i in 0..nx {
+        for j in 0..ny {
+            for k in 0..nz {
+                let g = |a: usize, b: usize| gradient[[3 * a + b, i, j, k]];
+
+                // strain rate and rotation rate tensors
+                let mut strain = [[0.; 3]; 3];
+                let mut rotation = [[0.; 3]; 3];
+                for a in 0..3 {
+                    for b in 0..3 {
+                        strain[a][b] = 0.5 * (g(a, b) + g(b, a));
+                        rotation[a][b] = 0.5 * (g(a, b) - g(b, a));
+                    }
+                }
+
+                let mut strain_norm = 0.;
+                let mut rotation_norm = 0.;
+                let mut m = [[0.; 3]; 3];
+                for a in 0..3 {
+                    for b in 0..3 {
+                        strain_norm += strain[a][b].powi(2);
+                        rotation_norm += rotation[a][b].powi(2);
+
+                        for c in 0..3 {
+                            m[a][b] += strain[a][c] * strain[c][b] + rotation[a][c] * rotation[c][b];
+                        }
+                    }
+                }
+
+                q[[0, i, j, k]] = 0.5 * (rotation_norm - strain_norm);
+                lambda2[[0, i, j, k]] = symmetric_eigenvalues(m)[1];
+            }
+        }
+    }
+
+    (q, lambda2)
+}
+
+/// eigenvalues of a real symmetric 3x3 matrix in decreasing order, using the closed form
+/// trigonometric solution of the characteristic polynomial
+fn symmetric_eigenvalues(m: [[f64; 3]; 3]) -> [f64; 3] {
+    let off_diagonal = m[0][1].powi(2) + m[0][2].powi(2) + m[1][2].powi(2);
+    let mean = (m[0][0] + m[1][1] + m[2][2]) / 3.;
+
+    let p2 = (m[0][0] - mean).powi(2)
+        + (m[1][1] - mean).powi(2)
+        + (m[2][2] - mean).powi(2)
+        + 2. * off_diagonal;
+    let p = (p2 / 6.).sqrt();
+
+    if p == 0. {
+        return [mean; 3];
+    }
+
+    // b = (m - mean * I) / p
+    let mut b = m;
+    for (a, row) in b.iter_mut().enumerate() {
+        for (c, value) in row.iter_mut().enumerate() {
+            let diagonal = if a == c { mean } else { 0. };
+            *value = (*value - diagonal) / p;
+        }
+    }
+
+    let determinant = b[0][0] * (b[1][1] * b[2][2] - b[1][2] * b[2][1])
+        - b[0][1] * (b[1][0] * b[2][2] - b[1][2] * b[2][0])
+        + b[0][2] * (b[1][0] * b[2][1] - b[1][1] * b[2][0]);
+    let r = (determinant / 2.).clamp(-1., 1.);
+    let angle = r.acos() / 3.;
+
+    let largest = mean + 2. * p * angle.cos();
+    let smallest = mean + 2. * p * (angle + 2. * std::f64::consts::PI / 3.).cos();
+    let middle = 3. * mean - largest - smallest;
+
+    [largest, middle, smallest]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: [f64; 3], expected: [f64; 3]) {
+        for (a, e) in actual.iter().zip(&expected) {
+            assert!((a - e).abs() < 1e-12, "{actual:?} != {expected:?}");
+        }
+    }
+
+    #[test]
+    fn diagonal_matrix() {
+        let m = [[2., 0., 0.], [0., -3., 0.], [0., 0., 5.]];
+        assert_close(symmetric_eigenvalues(m), [5., 2., -3.]);
+
+        let repeated = [[1., 0., 0.], [0., 1., 0.], [0., 0., 1.]];
+        assert_close(symmetric_eigenvalues(repeated), [1.; 3]);
+    }
+
+    #[test]
+    fn rotated_diagonal_matrix() {
+        let diagonal = [4., -1., 0.5];
+
+        // rotation by `a` about z followed by `b` about x
+        let (a, b) = (0.7_f64, -1.2_f64);
+        let rz = [[a.cos(), -a.sin(), 0.], [a.sin(), a.cos(), 0.], [0., 0., 1.]];
+        let rx = [[1., 0., 0.], [0., b.cos(), -b.sin()], [0., b.sin(), b.cos()]];
+        let mut r = [[0.; 3]; 3];
+        for i in 0..3 {
+            for j in 0..3 {
+                r[i][j] = (0..3).map(|k| rx[i][k] * rz[k][j]).sum();
+            }
+        }
+
+        // m = R diag(d) Rᵀ
+        let mut m = [[0.; 3]; 3];
+        for i in 0..3 {
+            for j in 0..3 {
+                m[i][j] = (0..3).map(|k| r[i][k] * diagonal[k] * r[j][k]).sum();
+            }
+        }
+
+        assert_close(symmetric_eigenvalues(m), [4., 0.5, -1.]);
+    }
+}
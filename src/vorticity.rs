@@ -0,0 +1,119 @@
+//! comparison of the supplied vorticity against the curl of the supplied velocity
+
+use anyhow::{bail, Result};
+use ndarray::Array4;
+use std::borrow::Cow;
+
+use crate::cli;
+use crate::coordinates;
+use crate::derivatives::{self, Differentiator};
+use crate::fields::{self, Field};
+use crate::grid;
+use crate::quadrature::Weights;
+use crate::writer::PointArray;
+
+/// compute the curl of the complex velocity and report how far it is from the supplied
+/// vorticity. Returns the difference `curl(u) - w` of the real and imaginary parts if
+/// `--write-vorticity-error` was given
+pub(crate) fn check_vorticity(
+    fields: &[Field],
+    axes: &grid::Axes,
+    args: &cli::Args,
+) -> Result<Vec<PointArray<'static>>> {
+    let velocity = fields::find_vector(fields, &args.velocity_field)?;
+    let vorticity = fields::find_vector(fields, &args.vorticity_field)?;
+
+    let differentiator = Differentiator::new(axes, args.fd_order)?;
+    let weights = Weights::trapezoid(axes);
+
+    let parts = [
+        ("real", Some(&velocity.real), Some(&vorticity.real)),
+        (
+            "imaginary",
+            velocity.imaginary.as_ref(),
+            vorticity.imaginary.as_ref(),
+        ),
+    ];
+
+    let mut arrays = Vec::new();
+
+    for (part, velocity_part, vorticity_part) in parts {
+        // a missing imaginary part is zero
+        if velocity_part.is_none() && vorticity_part.is_none() {
+            continue;
+        }
+
+        let mut error = match velocity_part {
+            Some(velocity_part) => derivatives::curl(&differentiator.gradient(velocity_part)),
+            None => Array4::zeros(vorticity.real.dim()),
+        };
+
+        if let Some(vorticity_part) = vorticity_part {
+            for (error, supplied) in error.iter_mut().zip(vorticity_part.iter()) {
+                *error -= supplied;
+            }
+        }
+
+        let squared_norm = |values: &Array4<f64>| {
+            weights.integrate(|i, j, k| (0..3).map(|v| values[[v, i, j, k]].powi(2)).sum())
+        };
+
+        let error_norm = squared_norm(&error).sqrt();
+        let max_error = error.iter().fold(0., |max: f64, value| max.max(value.abs()));
+
+        match vorticity_part.map(|supplied| squared_norm(supplied).sqrt()) {
+            Some(supplied_norm) if supplied_norm > 0. => println!(
+                "vorticity check ({part} part): relative L2 error {:.3e}, max pointwise error {max_error:.3e}",
+                error_norm / supplied_norm
+            ),
+            _ => println!(
+                "vorticity check ({part} part): supplied `{}` is zero, absolute L2 error {error_norm:.3e}, max pointwise error {max_error:.3e}",
+                args.vorticity_field
+            ),
+        }
+
+        if args.write_vorticity_error {
+            arrays.push(PointArray::owned(
+                format!("{part}_{}_error", args.vorticity_field),
+                error,
+            ));
+        }
+    }
+
+    Ok(arrays)
+}
+
+/// the supplied vorticity field, or the curl of the velocity field if the input does not have
+/// one
+pub(crate) fn supplied_or_computed<'a>(
+    fields: &'a [Field],
+    axes: &grid::Axes,
+    args: &cli::Args,
+) -> Result<Cow<'a, Field>> {
+    if fields.iter().any(|field| field.name == args.vorticity_field) {
+        return Ok(Cow::Borrowed(fields::find_vector(fields, &args.vorticity_field)?));
+    }
+
+    if let Some(geometry) = coordinates::non_cartesian(args) {
+        bail!(
+            "no `{}` field in the input, and the curl of `{}` can not be computed on {geometry}",
+            args.vorticity_field,
+            args.velocity_field
+        );
+    }
+
+    println!(
+        "no `{}` field in the input, using the curl of `{}` as the vorticity",
+        args.vorticity_field, args.velocity_field
+    );
+
+    let velocity = fields::find_vector(fields, &args.velocity_field)?;
+    let differentiator = Differentiator::new(axes, args.fd_order)?;
+    let curl = |values| derivatives::curl(&differentiator.gradient(values));
+
+    Ok(Cow::Owned(Field {
+        name: args.vorticity_field.clone(),
+        real: curl(&velocity.real),
+        imaginary: velocity.imaginary.as_ref().map(curl),
+    }))
+}
@@ -0,0 +1,590 @@
+//! VTK XML file writers
+//!
+//! every array is stored in a single raw appended data block after the XML headers, which keeps
+//! the files compact and fast for paraview to read
+
+use anyhow::{bail, Result};
+use ndarray::Array4;
+use std::borrow::Cow;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// a named array of point data with shape `(components, nx, ny, nz)`
+pub(crate) struct PointArray<'a> {
+    pub(crate) name: String,
+    pub(crate) values: Cow<'a, Array4<f64>>,
+}
+
+impl<'a> PointArray<'a> {
+    pub(crate) fn borrowed(name: impl Into<String>, values: &'a Array4<f64>) -> Self {
+        Self {
+            name: name.into(),
+            values: Cow::Borrowed(values),
+        }
+    }
+
+    pub(crate) fn owned(name: impl Into<String>, values: Array4<f64>) -> Self {
+        Self {
+            name: name.into(),
+            values: Cow::Owned(values),
+        }
+    }
+
+    /// a borrowed view of this array
+    pub(crate) fn reborrow(&self) -> PointArray<'_> {
+        PointArray::borrowed(self.name.clone(), &self.values)
+    }
+
+    pub(crate) fn components(&self) -> usize {
+        self.values.shape()[0]
+    }
+}
+
+/// the contents of one array in the appended data section
+enum Block<'a> {
+    /// point data, written with `x` varying fastest and the components of each point adjacent
+    Points(&'a Array4<f64>),
+    Float64(&'a [f64]),
+    Int64(&'a [i64]),
+}
+
+impl Block<'_> {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Block::Points(_) | Block::Float64(_) => "Float64",
+            Block::Int64(_) => "Int64",
+        }
+    }
+
+    fn bytes(&self) -> u64 {
+        let bytes = match self {
+            Block::Points(values) => values.len() * std::mem::size_of::<f64>(),
+            Block::Float64(values) => std::mem::size_of_val(*values),
+            Block::Int64(values) => std::mem::size_of_val(*values),
+        };
+
+        bytes as u64
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(&self.bytes().to_le_bytes())?;
+
+        match self {
+            Block::Points(values) => {
+                let [components, nx, ny, nz] = [
+                    values.shape()[0],
+                    values.shape()[1],
+                    values.shape()[2],
+                    values.shape()[3],
+                ];
+
+                for k in 0..nz {
+                    for j in 0..ny {
+                        for i in 0..nx {
+                            for v in 0..components {
+                                writer.write_all(&values[[v, i, j, k]].to_le_bytes())?;
+                            }
+                        }
+                    }
+                }
+            }
+            Block::Float64(values) => {
+                for value in *values {
+                    writer.write_all(&value.to_le_bytes())?;
+                }
+            }
+            Block::Int64(values) => {
+                for value in *values {
+                    writer.write_all(&value.to_le_bytes())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// keeps track of the offset of every array header so that the data can be appended at the end
+/// of the file
+struct Appended<'a> {
+    blocks: Vec<Block<'a>>,
+    offset: u64,
+}
+
+impl<'a> Appended<'a> {
+    fn new() -> Self {
+        Self {
+            blocks: Vec::new(),
+            offset: 0,
+        }
+    }
+
+    /// write the `<DataArray>` header for `block` and queue its contents
+    fn header<W: Write>(
+        &mut self,
+        writer: &mut W,
+        name: &str,
+        components: usize,
+        block: Block<'a>,
+    ) -> std::io::Result<()> {
+        writeln!(
+            writer,
+            r#"        <DataArray type="{}" Name="{}" NumberOfComponents="{components}" format="appended" offset="{}"/>"#,
+            block.type_name(),
+            escape(name),
+            self.offset
+        )?;
+
+        self.offset += std::mem::size_of::<u64>() as u64 + block.bytes();
+        self.blocks.push(block);
+
+        Ok(())
+    }
+
+    fn point_data<W: Write>(
+        &mut self,
+        writer: &mut W,
+        arrays: &'a [PointArray<'a>],
+    ) -> std::io::Result<()> {
+        writeln!(writer, "      <PointData>")?;
+        for array in arrays {
+            self.header(
+                writer,
+                &array.name,
+                array.components(),
+                Block::Points(&array.values),
+            )?;
+        }
+        writeln!(writer, "      </PointData>")
+    }
+
+    /// write the appended data section and close the file
+    fn finish<W: Write>(self, writer: &mut W) -> std::io::Result<()> {
+        writeln!(writer, r#"  <AppendedData encoding="raw">"#)?;
+        write!(writer, "_")?;
+        for block in &self.blocks {
+            block.write(writer)?;
+        }
+        writeln!(writer)?;
+        writeln!(writer, "  </AppendedData>")?;
+        writeln!(writer, "</VTKFile>")?;
+        writer.flush()
+    }
+}
+
+/// escape a string for use inside an XML attribute
+fn escape(text: &str) -> Cow<'_, str> {
+    if !text.contains(['&', '<', '>', '"']) {
+        return Cow::Borrowed(text);
+    }
+
+    Cow::Owned(
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;"),
+    )
+}
+
+fn file_header<W: Write>(writer: &mut W, kind: &str) -> std::io::Result<()> {
+    writeln!(writer, r#"<?xml version="1.0"?>"#)?;
+    writeln!(
+        writer,
+        r#"<VTKFile type="{kind}" version="1.0" byte_order="LittleEndian" header_type="UInt64">"#
+    )
+}
+
+/// the VTK extent of a grid with `nx`, `ny` and `nz` points, which must all be at least one
+fn extent(nx: usize, ny: usize, nz: usize) -> Result<String> {
+    if nx == 0 || ny == 0 || nz == 0 {
+        bail!("can not write a grid with ({nx},{ny},{nz}) points, every direction needs at least one point");
+    }
+
+    Ok(format!("0 {} 0 {} 0 {}", nx - 1, ny - 1, nz - 1))
+}
+
+/// write a `.vtr` rectilinear grid with the given axes and point data
+pub(crate) fn write_rectilinear<W: Write>(
+    mut writer: W,
+    x: &[f64],
+    y: &[f64],
+    z: &[f64],
+    arrays: &[PointArray],
+) -> Result<()> {
+    let extent = extent(x.len(), y.len(), z.len())?;
+    let mut appended = Appended::new();
+
+    file_header(&mut writer, "RectilinearGrid")?;
+    writeln!(writer, r#"  <RectilinearGrid WholeExtent="{extent}">"#)?;
+    writeln!(writer, r#"    <Piece Extent="{extent}">"#)?;
+
+    appended.point_data(&mut writer, arrays)?;
+
+    writeln!(writer, "      <Coordinates>")?;
+    for (name, axis) in [("x", x), ("y", y), ("z", z)] {
+        appended.header(&mut writer, name, 1, Block::Float64(axis))?;
+    }
+    writeln!(writer, "      </Coordinates>")?;
+
+    writeln!(writer, "    </Piece>")?;
+    writeln!(writer, "  </RectilinearGrid>")?;
+
+    appended.finish(&mut writer)?;
+
+    Ok(())
+}
+
+/// write a `.vts` structured grid with `lengths` points along each direction. `points` holds the
+/// interleaved `(x, y, z)` position of every point with the first direction varying fastest
+pub(crate) fn write_structured<W: Write>(
+    mut writer: W,
+    lengths: [usize; 3],
+    points: &[f64],
+    arrays: &[PointArray],
+) -> Result<()> {
+    let extent = extent(lengths[0], lengths[1], lengths[2])?;
+    let mut appended = Appended::new();
+
+    file_header(&mut writer, "StructuredGrid")?;
+    writeln!(writer, r#"  <StructuredGrid WholeExtent="{extent}">"#)?;
+    writeln!(writer, r#"    <Piece Extent="{extent}">"#)?;
+
+    appended.point_data(&mut writer, arrays)?;
+
+    writeln!(writer, "      <Points>")?;
+    appended.header(&mut writer, "Points", 3, Block::Float64(points))?;
+    writeln!(writer, "      </Points>")?;
+
+    writeln!(writer, "    </Piece>")?;
+    writeln!(writer, "  </StructuredGrid>")?;
+
+    appended.finish(&mut writer)?;
+
+    Ok(())
+}
+
+/// write a `.vti` image data file of a uniform grid with `lengths` points along each direction
+pub(crate) fn write_image<W: Write>(
+    mut writer: W,
+    origin: [f64; 3],
+    spacing: [f64; 3],
+    lengths: [usize; 3],
+    arrays: &[PointArray],
+) -> Result<()> {
+    let extent = extent(lengths[0], lengths[1], lengths[2])?;
+    let mut appended = Appended::new();
+
+    file_header(&mut writer, "ImageData")?;
+    writeln!(
+        writer,
+        r#"  <ImageData WholeExtent="{extent}" Origin="{} {} {}" Spacing="{} {} {}">"#,
+        origin[0], origin[1], origin[2], spacing[0], spacing[1], spacing[2]
+    )?;
+    writeln!(writer, r#"    <Piece Extent="{extent}">"#)?;
+
+    appended.point_data(&mut writer, arrays)?;
+
+    writeln!(writer, "    </Piece>")?;
+    writeln!(writer, "  </ImageData>")?;
+
+    appended.finish(&mut writer)?;
+
+    Ok(())
+}
+
+/// write a `.vtp` polydata file holding a single polyline through `points`, given as
+/// interleaved `(x, y, z)` coordinates in the order of the point data
+pub(crate) fn write_polyline<W: Write>(
+    writer: W,
+    points: &[f64],
+    arrays: &[PointArray],
+) -> Result<()> {
+    let count = points.len() / 3;
+    let connectivity: Vec<i64> = (0..count as i64).collect();
+
+    write_polydata(writer, points, "Lines", &connectivity, &[count as i64], arrays)
+}
+
+/// write a `.vtp` polydata file with a vertex at each of `points`, given as interleaved
+/// `(x, y, z)` coordinates in the order of the point data
+pub(crate) fn write_point_cloud<W: Write>(
+    writer: W,
+    points: &[f64],
+    arrays: &[PointArray],
+) -> Result<()> {
+    let count = points.len() / 3;
+    let connectivity: Vec<i64> = (0..count as i64).collect();
+    let offsets: Vec<i64> = (1..=count as i64).collect();
+
+    write_polydata(writer, points, "Verts", &connectivity, &offsets, arrays)
+}
+
+/// write a `.vtp` polydata file with a single kind of cell (`Verts` or `Lines`)
+fn write_polydata<W: Write>(
+    mut writer: W,
+    points: &[f64],
+    cells: &str,
+    connectivity: &[i64],
+    offsets: &[i64],
+    arrays: &[PointArray],
+) -> Result<()> {
+    let count = points.len() / 3;
+    let cell_count = |kind: &str| if kind == cells { offsets.len() } else { 0 };
+    let mut appended = Appended::new();
+
+    file_header(&mut writer, "PolyData")?;
+    writeln!(writer, "  <PolyData>")?;
+    writeln!(
+        writer,
+        r#"    <Piece NumberOfPoints="{count}" NumberOfVerts="{}" NumberOfLines="{}" NumberOfStrips="0" NumberOfPolys="0">"#,
+        cell_count("Verts"),
+        cell_count("Lines"),
+    )?;
+
+    appended.point_data(&mut writer, arrays)?;
+
+    writeln!(writer, "      <Points>")?;
+    appended.header(&mut writer, "Points", 3, Block::Float64(points))?;
+    writeln!(writer, "      </Points>")?;
+
+    writeln!(writer, "      <{cells}>")?;
+    appended.header(&mut writer, "connectivity", 1, Block::Int64(connectivity))?;
+    appended.header(&mut writer, "offsets", 1, Block::Int64(offsets))?;
+    writeln!(writer, "      </{cells}>")?;
+
+    writeln!(writer, "    </Piece>")?;
+    writeln!(writer, "  </PolyData>")?;
+
+    appended.finish(&mut writer)?;
+
+    Ok(())
+}
+
+/// the path of the `index`-th file of a series written next to `output`, e.g. `case_0003.vtr`
+/// for `case.vtr`
+pub(crate) fn numbered_path(output: &Path, index: usize) -> PathBuf {
+    let stem = output
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default();
+    let extension = output
+        .extension()
+        .map(|extension| extension.to_string_lossy())
+        .unwrap_or_else(|| "vtr".into());
+
+    output.with_file_name(format!("{stem}_{index:04}.{extension}"))
+}
+
+/// write a `.pvd` collection file listing `(time, file)` datasets. The files are referenced
+/// relative to the collection, so they should live in the same directory
+pub(crate) fn write_collection<W: Write>(mut writer: W, datasets: &[(f64, PathBuf)]) -> Result<()> {
+    writeln!(writer, r#"<?xml version="1.0"?>"#)?;
+    writeln!(
+        writer,
+        r#"<VTKFile type="Collection" version="1.0" byte_order="LittleEndian">"#
+    )?;
+    writeln!(writer, "  <Collection>")?;
+
+    for (time, file) in datasets {
+        let file = file
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+
+        writeln!(
+            writer,
+            r#"    <DataSet timestep="{time}" group="" part="0" file="{}"/>"#,
+            escape(&file)
+        )?;
+    }
+
+    writeln!(writer, "  </Collection>")?;
+    writeln!(writer, "</VTKFile>")?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a `<DataArray>` header of a written file
+    struct Header {
+        name: String,
+        kind: String,
+        components: usize,
+        offset: usize,
+    }
+
+    fn attribute<'a>(line: &'a str, name: &str) -> &'a str {
+        let start = line.find(&format!(r#" {name}=""#)).unwrap() + name.len() + 3;
+        line[start..].split('"').next().unwrap()
+    }
+
+    /// the XML before the appended data, the array headers and the raw appended bytes
+    fn split(file: &[u8]) -> (String, Vec<Header>, &[u8]) {
+        let marker = b"<AppendedData encoding=\"raw\">\n_";
+        let start = file
+            .windows(marker.len())
+            .position(|window| window == marker)
+            .unwrap();
+
+        let xml = String::from_utf8(file[..start].to_vec()).unwrap();
+        let headers = xml
+            .lines()
+            .filter(|line| line.contains("<DataArray"))
+            .map(|line| Header {
+                name: attribute(line, "Name").to_string(),
+                kind: attribute(line, "type").to_string(),
+                components: attribute(line, "NumberOfComponents").parse().unwrap(),
+                offset: attribute(line, "offset").parse().unwrap(),
+            })
+            .collect();
+
+        let tail = b"\n  </AppendedData>\n</VTKFile>\n";
+        assert!(file.ends_with(tail));
+
+        (
+            xml,
+            headers,
+            &file[start + marker.len()..file.len() - tail.len()],
+        )
+    }
+
+    /// the contents of the block at `offset`, checking its UInt64 byte count
+    fn block(raw: &[u8], offset: usize) -> &[u8] {
+        let bytes = u64::from_le_bytes(raw[offset..offset + 8].try_into().unwrap()) as usize;
+        &raw[offset + 8..offset + 8 + bytes]
+    }
+
+    fn floats(bytes: &[u8]) -> Vec<f64> {
+        bytes
+            .chunks_exact(8)
+            .map(|value| f64::from_le_bytes(value.try_into().unwrap()))
+            .collect()
+    }
+
+    fn integers(bytes: &[u8]) -> Vec<i64> {
+        bytes
+            .chunks_exact(8)
+            .map(|value| i64::from_le_bytes(value.try_into().unwrap()))
+            .collect()
+    }
+
+    /// every block follows the previous one, and the last block ends the appended data
+    fn check_contiguous(headers: &[Header], raw: &[u8]) {
+        let mut offset = 0;
+        for header in headers {
+            assert_eq!(header.offset, offset, "offset of `{}`", header.name);
+            offset += 8 + block(raw, offset).len();
+        }
+        assert_eq!(offset, raw.len());
+    }
+
+    #[test]
+    fn rectilinear_layout() {
+        // a vector with value 100 v + 10 i + j at component v of point (i, j)
+        let (nx, ny) = (3, 2);
+        let values: Vec<f64> = (0..3)
+            .flat_map(|v| {
+                (0..nx).flat_map(move |i| (0..ny).map(move |j| (100 * v + 10 * i + j) as f64))
+            })
+            .collect();
+        let vector = Array4::from_shape_vec((3, nx, ny, 1), values).unwrap();
+        let scalar = Array4::from_shape_vec((1, nx, ny, 1), vec![1., 2., 3., 4., 5., 6.]).unwrap();
+        let arrays = [
+            PointArray::borrowed("u", &vector),
+            PointArray::borrowed("p", &scalar),
+        ];
+
+        let mut file = Vec::new();
+        write_rectilinear(&mut file, &[0., 1., 2.], &[0., 0.5], &[4.], &arrays).unwrap();
+
+        let (xml, headers, raw) = split(&file);
+        assert!(xml.contains(r#"<RectilinearGrid WholeExtent="0 2 0 1 0 0">"#));
+        assert!(xml.contains(r#"header_type="UInt64""#));
+        check_contiguous(&headers, raw);
+
+        let names: Vec<_> = headers.iter().map(|header| header.name.as_str()).collect();
+        assert_eq!(names, ["u", "p", "x", "y", "z"]);
+        assert_eq!(headers[0].components, 3);
+        assert!(headers.iter().all(|header| header.kind == "Float64"));
+
+        // x varies fastest, with the components of each point adjacent
+        let u = floats(block(raw, headers[0].offset));
+        assert_eq!(u.len(), 3 * nx * ny);
+        assert_eq!(&u[..6], &[0., 100., 200., 10., 110., 210.]);
+        assert_eq!(&u[9..12], &[1., 101., 201.]);
+
+        assert_eq!(
+            floats(block(raw, headers[1].offset)),
+            [1., 3., 5., 2., 4., 6.]
+        );
+        assert_eq!(floats(block(raw, headers[3].offset)), [0., 0.5]);
+        assert_eq!(floats(block(raw, headers[4].offset)), [4.]);
+    }
+
+    #[test]
+    fn point_cloud_cells() {
+        let points = [0., 0., 0., 1., 0., 0., 0., 2., 0.];
+        let scalar = Array4::from_shape_vec((1, 3, 1, 1), vec![7., 8., 9.]).unwrap();
+
+        let mut file = Vec::new();
+        write_point_cloud(&mut file, &points, &[PointArray::borrowed("p", &scalar)]).unwrap();
+
+        let (xml, headers, raw) = split(&file);
+        assert!(xml.contains(r#"NumberOfPoints="3" NumberOfVerts="3" NumberOfLines="0""#));
+        assert!(xml.contains("<Verts>"));
+        check_contiguous(&headers, raw);
+
+        assert_eq!(headers[2].name, "connectivity");
+        assert_eq!(headers[2].kind, "Int64");
+        assert_eq!(integers(block(raw, headers[2].offset)), [0, 1, 2]);
+        assert_eq!(integers(block(raw, headers[3].offset)), [1, 2, 3]);
+        assert_eq!(floats(block(raw, headers[1].offset)), points);
+    }
+
+    #[test]
+    fn polyline_cells() {
+        let points = [0., 0., 0., 1., 0., 0., 3., 0., 0., 6., 0., 0.];
+
+        let mut file = Vec::new();
+        write_polyline(&mut file, &points, &[]).unwrap();
+
+        let (xml, headers, raw) = split(&file);
+        assert!(xml.contains(r#"NumberOfPoints="4" NumberOfVerts="0" NumberOfLines="1""#));
+        check_contiguous(&headers, raw);
+
+        let names: Vec<_> = headers.iter().map(|header| header.name.as_str()).collect();
+        assert_eq!(names, ["Points", "connectivity", "offsets"]);
+        assert_eq!(integers(block(raw, headers[1].offset)), [0, 1, 2, 3]);
+        assert_eq!(integers(block(raw, headers[2].offset)), [4]);
+    }
+
+    #[test]
+    fn structured_and_image_layout() {
+        let scalar = Array4::from_shape_vec((1, 2, 1, 1), vec![1., 2.]).unwrap();
+        let arrays = [PointArray::borrowed("p", &scalar)];
+
+        let mut file = Vec::new();
+        write_structured(&mut file, [2, 1, 1], &[0., 0., 0., 1., 1., 1.], &arrays).unwrap();
+        let (xml, headers, raw) = split(&file);
+        assert!(xml.contains(r#"<StructuredGrid WholeExtent="0 1 0 0 0 0">"#));
+        check_contiguous(&headers, raw);
+        assert_eq!(headers[1].components, 3);
+
+        let mut file = Vec::new();
+        write_image(&mut file, [0., 1., 2.], [0.5, 1., 1.], [2, 1, 1], &arrays).unwrap();
+        let (xml, headers, raw) = split(&file);
+        assert!(xml.contains(r#"Origin="0 1 2" Spacing="0.5 1 1""#));
+        check_contiguous(&headers, raw);
+        assert_eq!(floats(block(raw, headers[0].offset)), [1., 2.]);
+    }
+
+    #[test]
+    fn empty_grids_are_rejected() {
+        assert!(extent(0, 1, 1).is_err());
+        assert!(write_rectilinear(Vec::new(), &[], &[0.], &[0.], &[]).is_err());
+    }
+}